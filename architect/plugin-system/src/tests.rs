@@ -124,7 +124,7 @@ mod test {
 
         impl<T: 'static> TestHelper<T> {
             fn get_plugin_ref(&self) -> &T {
-                return self.pm.get_plugin_ref(&self.plugin_name).unwrap();
+                self.pm.get_plugin_ref(&self.plugin_name).unwrap()
             }
         }
 
@@ -0,0 +1,64 @@
+//! Sleeps created and dropped or pushed back before they fire, e.g. a
+//! timeout reset on every packet, then checks that the timer is as quick as
+//! ever to fire a fresh sleep.
+//!
+//! Run with `cargo bench -p async-runtime --bench timer_churn`.
+
+use std::time::{Duration, Instant};
+
+use async_runtime::{runtime::new_runtime, time::sleep};
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn main() {
+    let runtime = new_runtime(1, 1);
+
+    let elapsed = runtime.block_on(async {
+        let started = Instant::now();
+        for _ in 0..ITERATIONS {
+            let mut timeout = sleep(Duration::from_secs(60));
+            // registers with the timer, dropping cancels
+            assert!(futures::poll!(&mut timeout).is_pending());
+        }
+        started.elapsed()
+    });
+    report("create, poll and drop", elapsed);
+
+    let elapsed = runtime.block_on(async {
+        let mut timeout = sleep(Duration::from_secs(60));
+        let started = Instant::now();
+        for _ in 0..ITERATIONS {
+            timeout.reset(Instant::now() + Duration::from_secs(60));
+            assert!(futures::poll!(&mut timeout).is_pending());
+        }
+        started.elapsed()
+    });
+    report("reset later and poll", elapsed);
+
+    let elapsed = runtime.block_on(async {
+        let mut timeout = sleep(Duration::from_secs(60));
+        let started = Instant::now();
+        for i in 0..ITERATIONS {
+            // always earlier, so the entry has to move every time
+            timeout
+                .reset(Instant::now() + Duration::from_secs(60) - Duration::from_micros(i.into()));
+            assert!(futures::poll!(&mut timeout).is_pending());
+        }
+        started.elapsed()
+    });
+    report("reset earlier and poll", elapsed);
+
+    let latency = runtime.block_on(async {
+        let started = Instant::now();
+        sleep(Duration::from_millis(1)).await;
+        started.elapsed()
+    });
+    println!("1ms sleep after the churn took {latency:?}");
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{name}: {:?} per iteration over {ITERATIONS}",
+        elapsed / ITERATIONS
+    );
+}
@@ -0,0 +1,160 @@
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use futures::task::{waker, ArcWake};
+use log::info;
+
+use crate::{
+    metrics::Metrics,
+    runtime::{Task, WorkerControl},
+    time::Timer,
+};
+
+/// Settings of the worker auto-scaler, see `Builder::auto_scale`. The
+/// number of workers set with `Builder::worker_threads` is the upper bound.
+#[derive(Debug, Clone, Copy)]
+pub struct AutoScaleConfig {
+    /// Workers that are always in rotation, the runtime starts with that
+    /// many.
+    pub min_workers: usize,
+    /// Global queue depth above which the runtime is considered backlogged.
+    pub queue_threshold: usize,
+    /// How long the backlog must last before a worker is added. A backlog
+    /// that keeps going adds another worker every `grow_after`.
+    pub grow_after: Duration,
+    /// How long the queue must stay empty while some workers are parked
+    /// before a worker is taken out of rotation, one every `shrink_after`.
+    pub shrink_after: Duration,
+    /// How often the queue depth is sampled.
+    pub interval: Duration,
+}
+
+impl Default for AutoScaleConfig {
+    fn default() -> Self {
+        Self {
+            min_workers: 1,
+            queue_threshold: 64,
+            grow_after: Duration::from_millis(100),
+            shrink_after: Duration::from_secs(5),
+            interval: Duration::from_millis(10),
+        }
+    }
+}
+
+/// Grows and shrinks the set of workers in rotation with the queue depth.
+///
+/// Every worker thread is started up front, the ones out of rotation are
+/// drained like with `Handle::drain_worker` and stay parked, so scaling is
+/// just a matter of draining or resuming the last worker in rotation. The
+/// scaler owns the workers past `min_workers`, draining them by hand
+/// meanwhile confuses it.
+///
+/// It samples from the timer thread: each sample registers the next one as
+/// a deadline whose waker is the scaler itself.
+pub(crate) struct AutoScaler {
+    config: AutoScaleConfig,
+    // weak since the timer holds the scaler through its pending deadline
+    timer: Weak<Timer>,
+    workers: Arc<[Arc<WorkerControl>]>,
+    metrics: Arc<Metrics>,
+    global_queue: crossbeam_channel::Receiver<Arc<Task<'static>>>,
+    // never receives anything unless Builder::spawn_wake_ratio is set
+    wake_queue: crossbeam_channel::Receiver<Arc<Task<'static>>>,
+    state: Mutex<ScaleState>,
+}
+
+struct ScaleState {
+    // the workers below this index are in rotation
+    active: usize,
+    // when the current backlog, or idle stretch, started
+    backlogged_since: Option<Instant>,
+    idle_since: Option<Instant>,
+}
+
+impl AutoScaler {
+    /// Takes the workers past `min_workers` out of rotation, to be done
+    /// before the workers start, and starts sampling.
+    pub(crate) fn start(
+        config: AutoScaleConfig,
+        timer: &Arc<Timer>,
+        workers: Arc<[Arc<WorkerControl>]>,
+        metrics: Arc<Metrics>,
+        global_queue: crossbeam_channel::Receiver<Arc<Task<'static>>>,
+        wake_queue: crossbeam_channel::Receiver<Arc<Task<'static>>>,
+    ) {
+        let active = config.min_workers.min(workers.len());
+        for worker in &workers[active..] {
+            worker.drain();
+        }
+
+        let scaler = Arc::new(Self {
+            config,
+            timer: Arc::downgrade(timer),
+            workers,
+            metrics,
+            global_queue,
+            wake_queue,
+            state: Mutex::new(ScaleState {
+                active,
+                backlogged_since: None,
+                idle_since: None,
+            }),
+        });
+        scaler.schedule();
+    }
+
+    fn schedule(self: &Arc<Self>) {
+        // the runtime is gone otherwise
+        if let Some(timer) = self.timer.upgrade() {
+            timer.register(Instant::now() + self.config.interval, waker(self.clone()));
+        }
+    }
+
+    fn sample(&self) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let depth = self.global_queue.len() + self.wake_queue.len();
+        // the workers out of rotation are parked too
+        let parked = self
+            .metrics
+            .parked_workers()
+            .saturating_sub(self.workers.len() - state.active);
+
+        if depth > self.config.queue_threshold {
+            state.idle_since = None;
+            let since = *state.backlogged_since.get_or_insert(now);
+            if now - since >= self.config.grow_after && state.active < self.workers.len() {
+                self.workers[state.active].resume();
+                state.active += 1;
+                state.backlogged_since = Some(now);
+                self.metrics.workers_scaled_up();
+                info!(
+                    "queue depth {depth} above {}, scaled up to {} workers",
+                    self.config.queue_threshold, state.active
+                );
+            }
+        } else if depth == 0 && parked > 0 {
+            state.backlogged_since = None;
+            let since = *state.idle_since.get_or_insert(now);
+            if now - since >= self.config.shrink_after && state.active > self.config.min_workers {
+                state.active -= 1;
+                self.workers[state.active].drain();
+                state.idle_since = Some(now);
+                self.metrics.workers_scaled_down();
+                info!("runtime idle, scaled down to {} workers", state.active);
+            }
+        } else {
+            state.backlogged_since = None;
+            state.idle_since = None;
+        }
+    }
+}
+
+impl ArcWake for AutoScaler {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.sample();
+        arc_self.schedule();
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, AsyncRead, AsyncWrite, Sink, Stream};
+use pin_project_lite::pin_project;
+
+use super::{Decoder, Encoder};
+
+// how much the read buffer grows by for each read
+const READ_CHUNK: usize = 8 * 1024;
+
+// past this many buffered bytes, poll_ready flushes before accepting more
+const BACKPRESSURE_BOUNDARY: usize = 8 * 1024;
+
+pin_project! {
+    /// Adapts a byte stream into a `Stream` of frames decoded by the codec,
+    /// and a `Sink` of items encoded by it. Bytes are buffered on both
+    /// sides so frames can span several reads, and several frames can go
+    /// out in one write.
+    pub struct Framed<T, C> {
+        #[pin]
+        io: T,
+        codec: C,
+        read_buf: Vec<u8>,
+        write_buf: Vec<u8>,
+        // read_buf may hold a frame, try decoding before reading again
+        readable: bool,
+        eof: bool,
+        // the stream ended, either at EOF or on an error
+        done: bool,
+    }
+}
+
+impl<T, C> Framed<T, C> {
+    pub fn new(io: T, codec: C) -> Self {
+        Self {
+            io,
+            codec,
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+            readable: false,
+            eof: false,
+            done: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Returns the underlying byte stream. Bytes read but not decoded yet
+    /// and bytes encoded but not written yet are lost.
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+}
+
+impl<T, C> Stream for Framed<T, C>
+where
+    T: AsyncRead,
+    C: Decoder,
+{
+    type Item = Result<C::Item, C::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if *this.done {
+                return Poll::Ready(None);
+            }
+
+            if *this.readable {
+                let frame = if *this.eof {
+                    this.codec.decode_eof(this.read_buf)
+                } else {
+                    this.codec.decode(this.read_buf)
+                };
+
+                match frame {
+                    Ok(Some(frame)) => return Poll::Ready(Some(Ok(frame))),
+                    Ok(None) if *this.eof => {
+                        *this.done = true;
+                        return Poll::Ready(None);
+                    }
+                    // need more bytes
+                    Ok(None) => *this.readable = false,
+                    Err(error) => {
+                        *this.done = true;
+                        return Poll::Ready(Some(Err(error)));
+                    }
+                }
+            }
+
+            let len = this.read_buf.len();
+            this.read_buf.resize(len + READ_CHUNK, 0);
+            let read = this.io.as_mut().poll_read(cx, &mut this.read_buf[len..]);
+
+            let n = match read {
+                Poll::Ready(Ok(n)) => n,
+                Poll::Ready(Err(error)) => {
+                    this.read_buf.truncate(len);
+                    *this.done = true;
+                    return Poll::Ready(Some(Err(error.into())));
+                }
+                Poll::Pending => {
+                    this.read_buf.truncate(len);
+                    return Poll::Pending;
+                }
+            };
+
+            this.read_buf.truncate(len + n);
+            if n == 0 {
+                *this.eof = true;
+            }
+            *this.readable = true;
+        }
+    }
+}
+
+impl<T, C, I> Sink<I> for Framed<T, C>
+where
+    T: AsyncWrite,
+    C: Encoder<I>,
+{
+    type Error = C::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.write_buf.len() >= BACKPRESSURE_BOUNDARY {
+            return self.poll_flush(cx);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        let this = self.project();
+        this.codec.encode(item, this.write_buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        while !this.write_buf.is_empty() {
+            let n = ready!(this.io.as_mut().poll_write(cx, this.write_buf))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write frame to the stream",
+                )
+                .into()));
+            }
+            this.write_buf.drain(..n);
+        }
+
+        ready!(this.io.poll_flush(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        ready!(self.project().io.poll_close(cx))?;
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,82 @@
+use std::io;
+
+use super::{Decoder, Encoder};
+
+// size of the big-endian length prefix
+const HEADER_LEN: usize = 4;
+
+const DEFAULT_MAX_FRAME_LENGTH: usize = 8 * 1024 * 1024;
+
+/// Frames prefixed with their length as a big-endian `u32`, not counting
+/// the prefix itself.
+#[derive(Debug, Clone)]
+pub struct LengthDelimitedCodec {
+    max_frame_length: usize,
+}
+
+impl Default for LengthDelimitedCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LengthDelimitedCodec {
+    /// A codec accepting frames of up to 8MiB.
+    pub fn new() -> Self {
+        Self::with_max_frame_length(DEFAULT_MAX_FRAME_LENGTH)
+    }
+
+    /// Frames longer than `max_frame_length` are an `InvalidData` error, both
+    /// ways, so a corrupt prefix can't make the decoder buffer gigabytes.
+    pub fn with_max_frame_length(max_frame_length: usize) -> Self {
+        Self { max_frame_length }
+    }
+
+    fn too_long(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame longer than {} bytes", self.max_frame_length),
+        )
+    }
+}
+
+impl Decoder for LengthDelimitedCodec {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> io::Result<Option<Vec<u8>>> {
+        let Some(header) = src.get(..HEADER_LEN) else {
+            return Ok(None);
+        };
+
+        let len = u32::from_be_bytes(header.try_into().unwrap()) as usize;
+        if len > self.max_frame_length {
+            return Err(self.too_long());
+        }
+
+        if src.len() < HEADER_LEN + len {
+            // make room for the rest of the frame at once
+            src.reserve(HEADER_LEN + len - src.len());
+            return Ok(None);
+        }
+
+        let frame = src[HEADER_LEN..HEADER_LEN + len].to_vec();
+        src.drain(..HEADER_LEN + len);
+        Ok(Some(frame))
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for LengthDelimitedCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: T, dst: &mut Vec<u8>) -> io::Result<()> {
+        let frame = frame.as_ref();
+        if frame.len() > self.max_frame_length || frame.len() > u32::MAX as usize {
+            return Err(self.too_long());
+        }
+
+        dst.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        dst.extend_from_slice(frame);
+        Ok(())
+    }
+}
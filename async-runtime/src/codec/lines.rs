@@ -0,0 +1,102 @@
+use std::io;
+
+use super::{Decoder, Encoder};
+
+/// Splits a byte stream into UTF-8 lines. Lines end with `\n` and a
+/// trailing `\r` is dropped as well, a last line without `\n` is yielded at
+/// EOF. Encoded lines get a `\n` appended.
+#[derive(Debug, Clone)]
+pub struct LinesCodec {
+    max_length: usize,
+    // where to resume looking for the newline, so a long line isn't scanned
+    // again on every read
+    next_index: usize,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LinesCodecError {
+    #[error("line longer than the maximum length")]
+    MaxLineLengthExceeded,
+    #[error("line is not valid UTF-8")]
+    InvalidUtf8,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl Default for LinesCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LinesCodec {
+    pub fn new() -> Self {
+        Self::with_max_length(usize::MAX)
+    }
+
+    /// Lines longer than `max_length` bytes, not counting the line ending,
+    /// fail with `LinesCodecError::MaxLineLengthExceeded` instead of being
+    /// buffered forever.
+    pub fn with_max_length(max_length: usize) -> Self {
+        Self {
+            max_length,
+            next_index: 0,
+        }
+    }
+
+    fn take_line(&mut self, src: &mut Vec<u8>, len: usize) -> Result<String, LinesCodecError> {
+        self.next_index = 0;
+
+        let mut line: Vec<u8> = src.drain(..len).collect();
+        if src.first() == Some(&b'\n') {
+            src.remove(0);
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        if line.len() > self.max_length {
+            return Err(LinesCodecError::MaxLineLengthExceeded);
+        }
+
+        String::from_utf8(line).map_err(|_| LinesCodecError::InvalidUtf8)
+    }
+}
+
+impl Decoder for LinesCodec {
+    type Item = String;
+    type Error = LinesCodecError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<String>, LinesCodecError> {
+        match src[self.next_index..].iter().position(|b| *b == b'\n') {
+            Some(offset) => self.take_line(src, self.next_index + offset).map(Some),
+            // the line ending may not count towards the limit so allow one
+            // more byte for a \r
+            None if src.len() > self.max_length.saturating_add(1) => {
+                Err(LinesCodecError::MaxLineLengthExceeded)
+            }
+            None => {
+                self.next_index = src.len();
+                Ok(None)
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut Vec<u8>) -> Result<Option<String>, LinesCodecError> {
+        match self.decode(src)? {
+            Some(line) => Ok(Some(line)),
+            None if src.is_empty() => Ok(None),
+            None => self.take_line(src, src.len()).map(Some),
+        }
+    }
+}
+
+impl<T: AsRef<str>> Encoder<T> for LinesCodec {
+    type Error = LinesCodecError;
+
+    fn encode(&mut self, line: T, dst: &mut Vec<u8>) -> Result<(), LinesCodecError> {
+        dst.extend_from_slice(line.as_ref().as_bytes());
+        dst.push(b'\n');
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+//! Turning byte streams into streams of frames and back, see `Framed`.
+
+mod framed;
+mod length_delimited;
+mod lines;
+
+use std::io;
+
+pub use framed::Framed;
+pub use length_delimited::LengthDelimitedCodec;
+pub use lines::{LinesCodec, LinesCodecError};
+
+/// Decodes the frames of a byte stream.
+pub trait Decoder {
+    type Item;
+    type Error: From<io::Error>;
+
+    /// Decodes the frame at the start of `src` and removes its bytes from
+    /// the buffer. Returns `Ok(None)` when `src` doesn't hold a full frame
+    /// yet, it's called again once more bytes are read.
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Self::Item>, Self::Error>;
+
+    /// Same as `decode` but called once the stream reached EOF, so there's
+    /// no more bytes coming. By default leftover bytes that don't make a
+    /// frame are an `UnexpectedEof` error.
+    fn decode_eof(&mut self, src: &mut Vec<u8>) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if src.is_empty() => Ok(None),
+            None => {
+                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "partial frame at EOF").into())
+            }
+        }
+    }
+}
+
+/// Encodes items into the frames of a byte stream.
+pub trait Encoder<Item> {
+    type Error: From<io::Error>;
+
+    /// Appends the frame for `item` to `dst`.
+    fn encode(&mut self, item: Item, dst: &mut Vec<u8>) -> Result<(), Self::Error>;
+}
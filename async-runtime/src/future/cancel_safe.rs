@@ -0,0 +1,163 @@
+#[cfg(debug_assertions)]
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex},
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+#[cfg(debug_assertions)]
+use log::error;
+use pin_project_lite::pin_project;
+
+// the critical sections opened by the future of the innermost cancel_safe
+// being polled on this thread
+#[cfg(debug_assertions)]
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Sections>>> = const { RefCell::new(None) };
+}
+
+#[cfg(debug_assertions)]
+type Sections = Mutex<Vec<&'static str>>;
+
+// what a CancelSafe keeps track of the open sections with, nothing in
+// release builds
+#[cfg(debug_assertions)]
+type Tracker = Arc<Sections>;
+#[cfg(not(debug_assertions))]
+type Tracker = ();
+
+/// Watches `future` for being dropped in the middle of a critical section,
+/// i.e. while it holds a `CriticalSection` guard across an await. That's a
+/// cancellation bug: whatever the section was in the middle of, e.g. a
+/// write split into two syscalls or a state update spread over two awaits,
+/// is left half done. The drop is reported as an error log naming the open
+/// sections, the future is dropped all the same.
+///
+/// Works wherever the future gets dropped: a losing branch of `select!`, an
+/// elapsed `time::timeout`, or an aborted task when spawned as
+/// `handle.spawn(cancel_safe(future))`. Only checked in debug builds, the
+/// wrapper and the guards do nothing in release builds.
+pub fn cancel_safe<F: Future>(future: F) -> CancelSafe<F> {
+    CancelSafe {
+        future,
+        sections: Tracker::default(),
+    }
+}
+
+/// Opens a critical section of the future being polled, closed when the
+/// guard is dropped: the future must not be dropped while it holds the
+/// guard, see `cancel_safe`. `name` identifies the section in the report.
+///
+/// Outside of a future wrapped in `cancel_safe`, the guard does nothing.
+pub fn critical_section(name: &'static str) -> CriticalSection {
+    #[cfg(debug_assertions)]
+    {
+        let sections = CURRENT.with(|current| current.borrow().clone());
+        if let Some(sections) = &sections {
+            sections.lock().unwrap().push(name);
+        }
+        CriticalSection { name, sections }
+    }
+
+    #[cfg(not(debug_assertions))]
+    {
+        let _ = name;
+        CriticalSection {}
+    }
+}
+
+/// Guard returned by `critical_section`, the section ends when it's
+/// dropped.
+#[must_use = "the critical section ends as soon as the guard is dropped"]
+pub struct CriticalSection {
+    #[cfg(debug_assertions)]
+    name: &'static str,
+    // None outside of a cancel_safe future
+    #[cfg(debug_assertions)]
+    sections: Option<Arc<Sections>>,
+}
+
+#[cfg(debug_assertions)]
+impl Drop for CriticalSection {
+    fn drop(&mut self) {
+        if let Some(sections) = &self.sections {
+            let mut sections = sections.lock().unwrap();
+            if let Some(index) = sections.iter().rposition(|name| *name == self.name) {
+                sections.remove(index);
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Future returned by `cancel_safe`.
+    pub struct CancelSafe<F> {
+        #[pin]
+        future: F,
+        sections: Tracker,
+    }
+
+    impl<F> PinnedDrop for CancelSafe<F> {
+        fn drop(this: Pin<&mut Self>) {
+            // runs before the future is dropped, so the guards it holds are
+            // still there
+            #[cfg(debug_assertions)]
+            {
+                let sections = this.sections.lock().unwrap();
+                if !sections.is_empty() {
+                    error!(
+                        "future dropped in the middle of critical sections {:?}",
+                        *sections
+                    );
+                }
+            }
+
+            #[cfg(not(debug_assertions))]
+            let _ = this;
+        }
+    }
+}
+
+impl<F> CancelSafe<F> {
+    /// The critical sections the future is in right now, innermost last.
+    /// Always empty in release builds.
+    pub fn critical_sections(&self) -> Vec<&'static str> {
+        #[cfg(debug_assertions)]
+        return self.sections.lock().unwrap().clone();
+
+        #[cfg(not(debug_assertions))]
+        Vec::new()
+    }
+}
+
+impl<F: Future> Future for CancelSafe<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        #[cfg(debug_assertions)]
+        {
+            // restores the outer wrapper's sections even if the poll panics
+            struct Restore(Option<Arc<Sections>>);
+
+            impl Drop for Restore {
+                fn drop(&mut self) {
+                    CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+                }
+            }
+
+            let _restore = Restore(
+                CURRENT.with(|current| current.borrow_mut().replace(this.sections.clone())),
+            );
+            this.future.poll(cx)
+        }
+
+        #[cfg(not(debug_assertions))]
+        this.future.poll(cx)
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FuturesUnordered, Stream};
+use pin_project_lite::pin_project;
+
+/// Runs `futures` to completion with at most `limit` of them in flight at
+/// once, and resolves to their outputs in the order of `futures`, whatever
+/// order they complete in. Unlike `futures::future::join_all`, which polls
+/// every future from the start, the peak resources held, e.g. connections
+/// or buffers, are bounded by `limit` whatever the size of the batch.
+///
+/// The next future is taken from the iterator only when there's room for
+/// it, so with a lazy iterator such as `ids.iter().map(fetch)` the futures
+/// aren't even created before they can run. They run within this future,
+/// concurrently but not in parallel.
+///
+/// Panics if `limit` is zero.
+pub fn join_all_buffered<I>(futures: I, limit: usize) -> JoinAllBuffered<I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    assert!(limit > 0, "limit must be greater than zero");
+    JoinAllBuffered {
+        futures: Some(futures.into_iter()),
+        running: FuturesUnordered::new(),
+        outputs: Vec::new(),
+        limit,
+        done: false,
+    }
+}
+
+pub struct JoinAllBuffered<I>
+where
+    I: Iterator,
+    I::Item: Future,
+{
+    // None once every future was started
+    futures: Option<I>,
+    running: FuturesUnordered<Indexed<I::Item>>,
+    // in input order, None for the futures still running
+    outputs: Vec<Option<<I::Item as Future>::Output>>,
+    limit: usize,
+    done: bool,
+}
+
+// the iterator is never pinned, the futures are pinned in FuturesUnordered
+impl<I> Unpin for JoinAllBuffered<I>
+where
+    I: Iterator,
+    I::Item: Future,
+{
+}
+
+impl<I> Future for JoinAllBuffered<I>
+where
+    I: Iterator,
+    I::Item: Future,
+{
+    type Output = Vec<<I::Item as Future>::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        assert!(!this.done, "JoinAllBuffered polled after completion");
+
+        loop {
+            while this.running.len() < this.limit {
+                let Some(future) = this.futures.as_mut().and_then(Iterator::next) else {
+                    this.futures = None;
+                    break;
+                };
+                this.running.push(Indexed {
+                    index: this.outputs.len(),
+                    future,
+                });
+                this.outputs.push(None);
+            }
+
+            match Pin::new(&mut this.running).poll_next(cx) {
+                // a slot freed up, start the next one
+                Poll::Ready(Some((index, output))) => this.outputs[index] = Some(output),
+                // nothing running means every future was started
+                Poll::Ready(None) => {
+                    this.done = true;
+                    let outputs = std::mem::take(&mut this.outputs);
+                    return Poll::Ready(outputs.into_iter().map(Option::unwrap).collect());
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+pin_project! {
+    // a future along with its position in the input
+    struct Indexed<F> {
+        index: usize,
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F: Future> Future for Indexed<F> {
+    type Output = (usize, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let output = std::task::ready!(this.future.poll(cx));
+        Poll::Ready((*this.index, output))
+    }
+}
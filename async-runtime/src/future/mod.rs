@@ -0,0 +1,11 @@
+//! Extra future combinators on top of `std::future::Future`.
+
+mod cancel_safe;
+mod join_all;
+mod option;
+mod select_all;
+
+pub use cancel_safe::{cancel_safe, critical_section, CancelSafe, CriticalSection};
+pub use join_all::{join_all_buffered, JoinAllBuffered};
+pub use option::OptionFuture;
+pub use select_all::{select_all_cancel, SelectAllCancel};
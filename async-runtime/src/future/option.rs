@@ -0,0 +1,82 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::future::FusedFuture;
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// A future that may not be there: awaits the inner future and yields
+    /// `Some` of its output, or yields `None` right away if there's none.
+    /// Handy for a `select!` branch that's only enabled sometimes, e.g. a
+    /// deadline that's only set for some requests:
+    ///
+    /// ```no_run
+    /// use std::{future::Future, time::Instant};
+    ///
+    /// use async_runtime::{future::OptionFuture, select, time::sleep_until};
+    /// use futures::FutureExt;
+    ///
+    /// async fn respond(
+    ///     response: impl Future<Output = u32>,
+    ///     deadline: Option<Instant>,
+    /// ) -> Option<u32> {
+    ///     let deadline: OptionFuture<_> = deadline.map(sleep_until).into();
+    ///     select! {
+    ///         response = response.fuse() => Some(response),
+    ///         _ = deadline => None,
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// It's terminated when there's no inner future, so `select!` skips the
+    /// branch then. Yields `None` again if polled after completion.
+    pub struct OptionFuture<F> {
+        #[pin]
+        inner: Option<F>,
+    }
+}
+
+impl<F> OptionFuture<F> {
+    /// Whether there's no inner future, either from the start or because it
+    /// completed.
+    pub fn is_none(&self) -> bool {
+        self.inner.is_none()
+    }
+}
+
+impl<F> From<Option<F>> for OptionFuture<F> {
+    fn from(inner: Option<F>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<F> Default for OptionFuture<F> {
+    fn default() -> Self {
+        Self { inner: None }
+    }
+}
+
+impl<F: Future> Future for OptionFuture<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut inner = self.project().inner;
+
+        let Some(future) = inner.as_mut().as_pin_mut() else {
+            return Poll::Ready(None);
+        };
+
+        let output = std::task::ready!(future.poll(cx));
+        inner.set(None);
+        Poll::Ready(Some(output))
+    }
+}
+
+impl<F: Future> FusedFuture for OptionFuture<F> {
+    fn is_terminated(&self) -> bool {
+        self.inner.is_none()
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Waits for the first of `futures` to complete and resolves to its output
+/// along with its index. Unlike `futures::future::select_all`, the other
+/// futures are dropped right away instead of being handed back, which
+/// cancels them at whatever await point they were suspended at. Meant for
+/// losers that have no side effect worth finishing.
+///
+/// The futures are polled in order, so when several are ready at once the
+/// one with the lowest index wins. Panics if `futures` is empty.
+pub fn select_all_cancel<I>(futures: I) -> SelectAllCancel<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    let futures: Vec<_> = futures.into_iter().map(Box::pin).collect();
+    assert!(
+        !futures.is_empty(),
+        "select_all_cancel needs at least one future"
+    );
+    SelectAllCancel { futures }
+}
+
+pub struct SelectAllCancel<F> {
+    // boxed so that they don't need to be Unpin, emptied once one completes
+    futures: Vec<Pin<Box<F>>>,
+}
+
+impl<F: Future> Future for SelectAllCancel<F> {
+    type Output = (F::Output, usize);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        assert!(
+            !self.futures.is_empty(),
+            "SelectAllCancel polled after completion"
+        );
+
+        let ready = self
+            .futures
+            .iter_mut()
+            .enumerate()
+            .find_map(|(index, future)| match future.as_mut().poll(cx) {
+                Poll::Ready(output) => Some((output, index)),
+                Poll::Pending => None,
+            });
+
+        match ready {
+            Some(ready) => {
+                // none of them is being polled anymore, so they're all
+                // suspended at an await point and safe to drop
+                self.futures.clear();
+                Poll::Ready(ready)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
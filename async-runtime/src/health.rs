@@ -0,0 +1,117 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use crate::metrics::RuntimeMetrics;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthStatus {
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+/// Limits used by `Handle::health` to assess the runtime, override them with
+/// `Builder::health_thresholds`.
+#[derive(Debug, Clone, Copy)]
+pub struct HealthThresholds {
+    /// Global queue depth at which the runtime is considered degraded.
+    pub degraded_queue_depth: usize,
+    /// Global queue depth at which the runtime is considered unhealthy.
+    pub unhealthy_queue_depth: usize,
+    /// How much the global queue depth has to grow over the last
+    /// `trend_samples` calls of `health` for the runtime to be considered
+    /// degraded.
+    pub degraded_queue_growth: usize,
+    /// Same as `degraded_queue_growth`, for unhealthy.
+    pub unhealthy_queue_growth: usize,
+    /// How many calls of `health`, the current one included, the queue depth
+    /// trend is followed over. Below 2 there's no trend.
+    pub trend_samples: usize,
+    /// Parked workers at which the runtime is considered degraded while
+    /// tasks have been waiting in the global queue in each of the last
+    /// `trend_samples` samples, i.e. the workers aren't woken up for them or
+    /// were drained.
+    pub degraded_parked_workers: usize,
+    /// Dead workers at which the runtime is considered degraded.
+    pub degraded_dead_workers: usize,
+    /// Dead workers at which the runtime is considered unhealthy.
+    pub unhealthy_dead_workers: usize,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            degraded_queue_depth: 1024,
+            unhealthy_queue_depth: 16384,
+            degraded_queue_growth: 256,
+            unhealthy_queue_growth: 4096,
+            trend_samples: 8,
+            degraded_parked_workers: 1,
+            degraded_dead_workers: 1,
+            // only once they're all dead
+            unhealthy_dead_workers: usize::MAX,
+        }
+    }
+}
+
+impl HealthStatus {
+    /// `queue_depths` are the global queue depths sampled by the last calls
+    /// of `health`, oldest first, ending with the current one.
+    ///
+    /// - `Unhealthy` when the global queue depth or its growth over the
+    ///   samples reaches the unhealthy threshold, when
+    ///   `unhealthy_dead_workers` workers have died, or when every worker
+    ///   has.
+    /// - `Degraded` when the global queue depth or its growth reaches the
+    ///   degraded threshold, when `degraded_dead_workers` workers have died,
+    ///   or when `degraded_parked_workers` workers are parked although tasks
+    ///   were queued in a full window of samples.
+    /// - `Healthy` otherwise.
+    ///
+    /// Workers are never restarted, a worker dies when a task panics on it.
+    pub(crate) fn assess(
+        metrics: &RuntimeMetrics,
+        queue_depths: &[usize],
+        num_workers: usize,
+        thresholds: &HealthThresholds,
+    ) -> Self {
+        let growth = match (queue_depths.first(), queue_depths.last()) {
+            (Some(first), Some(last)) => last.saturating_sub(*first),
+            _ => 0,
+        };
+        let dead_workers = num_workers.saturating_sub(metrics.live_workers);
+        let backlog = queue_depths.len() >= thresholds.trend_samples
+            && queue_depths.iter().all(|depth| *depth > 0);
+
+        if metrics.global_queue_depth >= thresholds.unhealthy_queue_depth
+            || growth >= thresholds.unhealthy_queue_growth
+            || dead_workers >= thresholds.unhealthy_dead_workers
+            || metrics.live_workers == 0
+        {
+            HealthStatus::Unhealthy
+        } else if metrics.global_queue_depth >= thresholds.degraded_queue_depth
+            || growth >= thresholds.degraded_queue_growth
+            || dead_workers >= thresholds.degraded_dead_workers
+            || (backlog && metrics.parked_workers >= thresholds.degraded_parked_workers)
+        {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Healthy
+        }
+    }
+}
+
+/// The global queue depths sampled by `Handle::health`, for the trend.
+#[derive(Default)]
+pub(crate) struct QueueDepthHistory(Mutex<VecDeque<usize>>);
+
+impl QueueDepthHistory {
+    /// Records `depth` and returns the last `len` samples, oldest first.
+    pub(crate) fn sample(&self, depth: usize, len: usize) -> Vec<usize> {
+        let mut samples = self.0.lock().unwrap();
+        samples.push_back(depth);
+        while samples.len() > len.max(1) {
+            samples.pop_front();
+        }
+        samples.iter().copied().collect()
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc, Mutex, Weak,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+
+use crate::runtime::{Task, TaskId};
+
+pub(crate) type HeartbeatCallback = dyn Fn(&[TaskHeartbeat]) + Send + Sync;
+
+/// What a live task was doing when the heartbeat was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// Waiting to be woken.
+    Idle,
+    /// Woken and waiting in a queue for a worker.
+    Queued,
+    /// Being polled by a worker.
+    Running,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskHeartbeat {
+    pub id: TaskId,
+    /// Set with `Handle::spawn_named`.
+    pub name: Option<String>,
+    pub state: TaskState,
+    /// How long the task has been in `state`.
+    pub since: Duration,
+    /// How many times the task has been polled so far.
+    pub polls: u64,
+    /// Whether the task looks stuck: it has been `Running` (a poll that
+    /// doesn't return) or `Queued` (woken but never polled) for longer than
+    /// the heartbeat interval. Being `Idle` for a long time is normal for a
+    /// task waiting on I/O or a timer and is never suspicious.
+    pub suspicious: bool,
+}
+
+const IDLE: u8 = 0;
+const QUEUED: u8 = 1;
+const RUNNING: u8 = 2;
+const DONE: u8 = 3;
+
+/// Per-task state tracked for the heartbeat and the task registry, only
+/// present on tasks when either of them is enabled.
+pub(crate) struct TaskWatch {
+    state: AtomicU8,
+    // nanoseconds since the task was spawned at which the state last changed
+    since: AtomicU64,
+    polls: AtomicU64,
+    pub(crate) spawned_at: Instant,
+}
+
+impl TaskWatch {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: AtomicU8::new(QUEUED),
+            since: AtomicU64::new(0),
+            polls: AtomicU64::new(0),
+            spawned_at: Instant::now(),
+        }
+    }
+
+    fn touch(&self) {
+        let now = self.spawned_at.elapsed().as_nanos() as u64;
+        self.since.store(now, Ordering::Relaxed);
+    }
+
+    fn set(&self, state: u8) {
+        self.state.store(state, Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub(crate) fn queued(&self) {
+        self.set(QUEUED);
+    }
+
+    pub(crate) fn running(&self) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.set(RUNNING);
+    }
+
+    pub(crate) fn idle(&self) {
+        // a wake that came in while polling already moved the task back to
+        // the queue, keep it that way
+        if self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.touch();
+        }
+    }
+
+    pub(crate) fn done(&self) {
+        self.set(DONE);
+    }
+
+    pub(crate) fn polls(&self) -> u64 {
+        self.polls.load(Ordering::Relaxed)
+    }
+
+    /// The current state and for how long the task has been in it, `None`
+    /// once the task is done.
+    pub(crate) fn state(&self) -> Option<(TaskState, Duration)> {
+        let state = match self.state.load(Ordering::Relaxed) {
+            IDLE => TaskState::Idle,
+            QUEUED => TaskState::Queued,
+            RUNNING => TaskState::Running,
+            _ => return None,
+        };
+
+        // the state may change in the meantime, which would put `since` after
+        // the elapsed time
+        let since = self
+            .spawned_at
+            .elapsed()
+            .saturating_sub(Duration::from_nanos(self.since.load(Ordering::Relaxed)));
+
+        Some((state, since))
+    }
+
+    fn heartbeat(&self, task: &Task<'static>, interval: Duration) -> Option<TaskHeartbeat> {
+        let (state, since) = self.state()?;
+
+        Some(TaskHeartbeat {
+            id: task.id,
+            name: task.name.clone(),
+            state,
+            since,
+            polls: self.polls(),
+            suspicious: state != TaskState::Idle && since > interval,
+        })
+    }
+}
+
+/// Live tasks watched by the heartbeat thread.
+pub(crate) struct Watchdog {
+    tasks: Mutex<Vec<Weak<Task<'static>>>>,
+}
+
+impl Watchdog {
+    /// Starts the heartbeat thread that reports the live tasks to `callback`
+    /// every `interval`. The thread exits once the runtime is gone.
+    pub(crate) fn start(interval: Duration, callback: Arc<HeartbeatCallback>) -> Arc<Self> {
+        let watchdog = Arc::new(Self {
+            tasks: Mutex::new(Vec::new()),
+        });
+
+        let weak = Arc::downgrade(&watchdog);
+
+        thread::Builder::new()
+            .name("heartbeat".into())
+            .spawn(move || loop {
+                thread::sleep(interval);
+
+                let Some(watchdog) = weak.upgrade() else {
+                    debug!("runtime dropped, heartbeat thread exiting");
+                    break;
+                };
+
+                let heartbeats = watchdog.heartbeats(interval);
+                callback(&heartbeats);
+            })
+            .unwrap();
+
+        watchdog
+    }
+
+    pub(crate) fn register(&self, task: &Arc<Task<'static>>) {
+        self.tasks.lock().unwrap().push(Arc::downgrade(task));
+    }
+
+    fn heartbeats(&self, interval: Duration) -> Vec<TaskHeartbeat> {
+        let mut heartbeats = Vec::new();
+
+        // forget about the tasks that finished along the way
+        self.tasks.lock().unwrap().retain(|task| {
+            let Some(task) = task.upgrade() else {
+                return false;
+            };
+            let Some(watch) = &task.watch else {
+                return false;
+            };
+
+            match watch.heartbeat(&task, interval) {
+                Some(heartbeat) => {
+                    heartbeats.push(heartbeat);
+                    true
+                }
+                None => false,
+            }
+        });
+
+        heartbeats
+    }
+}
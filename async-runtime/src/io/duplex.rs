@@ -0,0 +1,168 @@
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use futures::{AsyncRead, AsyncWrite};
+
+use crate::task::{with_wake_source, WakeSource};
+
+/// Creates two connected in-memory byte streams, like a socket pair. Bytes
+/// written to one are read from the other. Each direction buffers up to
+/// `capacity` bytes, past that writes wait for the other side to read.
+///
+/// Closing one side with `AsyncWriteExt::close` only shuts its write half:
+/// the other side reads EOF once the buffered bytes are consumed but can
+/// still write back. Dropping a side closes both halves: the other side reads
+/// EOF and its writes fail with `BrokenPipe`.
+///
+/// Meant for testing protocol code without real sockets.
+pub fn duplex(capacity: usize) -> (DuplexStream, DuplexStream) {
+    assert!(capacity > 0, "duplex capacity must be greater than zero");
+
+    let one_to_two = Arc::new(Mutex::new(Pipe::new(capacity)));
+    let two_to_one = Arc::new(Mutex::new(Pipe::new(capacity)));
+
+    let one = DuplexStream {
+        read: two_to_one.clone(),
+        write: one_to_two.clone(),
+    };
+    let two = DuplexStream {
+        read: one_to_two,
+        write: two_to_one,
+    };
+
+    (one, two)
+}
+
+pub struct DuplexStream {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+// one direction of a duplex
+struct Pipe {
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    // the writer shut down or was dropped, nothing more is coming
+    write_closed: bool,
+    // the reader was dropped, writing is pointless
+    read_closed: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            write_closed: false,
+            read_closed: false,
+            read_waker: None,
+            write_waker: None,
+        }
+    }
+
+    fn close_write(&mut self) {
+        self.write_closed = true;
+        if let Some(waker) = self.read_waker.take() {
+            with_wake_source(WakeSource::Io, || waker.wake());
+        }
+    }
+
+    fn close_read(&mut self) {
+        self.read_closed = true;
+        if let Some(waker) = self.write_waker.take() {
+            with_wake_source(WakeSource::Io, || waker.wake());
+        }
+    }
+}
+
+impl AsyncRead for DuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.read.lock().unwrap();
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        if pipe.buffer.is_empty() {
+            if pipe.write_closed {
+                return Poll::Ready(Ok(0));
+            }
+
+            pipe.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = buf.len().min(pipe.buffer.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.buffer.drain(..n)) {
+            *dst = src;
+        }
+
+        // there's room for the writer again
+        if let Some(waker) = pipe.write_waker.take() {
+            with_wake_source(WakeSource::Io, || waker.wake());
+        }
+
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for DuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.write.lock().unwrap();
+
+        if pipe.read_closed || pipe.write_closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let available = pipe.capacity - pipe.buffer.len();
+        if available == 0 {
+            pipe.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = buf.len().min(available);
+        pipe.buffer.extend(&buf[..n]);
+
+        if let Some(waker) = pipe.read_waker.take() {
+            with_wake_source(WakeSource::Io, || waker.wake());
+        }
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // nothing is buffered on the writing side
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.write.lock().unwrap().close_write();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        self.write.lock().unwrap().close_write();
+        self.read.lock().unwrap().close_read();
+    }
+}
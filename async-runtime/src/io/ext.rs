@@ -0,0 +1,249 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, AsyncRead, AsyncWrite};
+
+use crate::task::AwaitSite;
+
+// how much read_to_end grows the buffer by at least for each read
+const MIN_READ_CHUNK: usize = 32;
+
+/// Convenience methods on top of `AsyncRead`.
+pub trait AsyncReadExt: AsyncRead {
+    /// Reads until EOF, appending the bytes to `buf`. Resolves to the number
+    /// of bytes read. On error the bytes read so far stay in `buf`.
+    #[track_caller]
+    fn read_to_end<'a>(&'a mut self, buf: &'a mut Vec<u8>) -> ReadToEnd<'a, Self>
+    where
+        Self: Unpin,
+    {
+        ReadToEnd {
+            reader: self,
+            start: buf.len(),
+            buf,
+            site: AwaitSite::new("read_to_end"),
+        }
+    }
+
+    /// Reads exactly enough bytes to fill `buf`. Fails with
+    /// `UnexpectedEof` if the stream ends first, in which case the contents
+    /// of `buf` are unspecified.
+    #[track_caller]
+    fn read_exact<'a>(&'a mut self, buf: &'a mut [u8]) -> ReadExact<'a, Self>
+    where
+        Self: Unpin,
+    {
+        ReadExact {
+            reader: self,
+            buf,
+            site: AwaitSite::new("read_exact"),
+        }
+    }
+}
+
+impl<R: AsyncRead + ?Sized> AsyncReadExt for R {}
+
+/// Convenience methods on top of `AsyncWrite`.
+pub trait AsyncWriteExt: AsyncWrite {
+    /// Writes the whole of `buf`, however many writes it takes. Fails with
+    /// `WriteZero` if the writer stops accepting bytes.
+    #[track_caller]
+    fn write_all<'a>(&'a mut self, buf: &'a [u8]) -> WriteAll<'a, Self>
+    where
+        Self: Unpin,
+    {
+        WriteAll {
+            writer: self,
+            buf,
+            site: AwaitSite::new("write_all"),
+        }
+    }
+
+    /// Flushes and shuts the writer down, see `AsyncWrite::poll_close`.
+    /// What a shut down writer means for the other end depends on the
+    /// writer, e.g. a `DuplexStream` only closes its write half.
+    #[track_caller]
+    fn close(&mut self) -> Close<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Close {
+            writer: self,
+            site: AwaitSite::new("close"),
+        }
+    }
+}
+
+impl<W: AsyncWrite + ?Sized> AsyncWriteExt for W {}
+
+// Retries interrupted operations right away. `WouldBlock` is not supposed
+// to come out of a poll function but some adapters over non-blocking
+// handles let it through, so yield and try again later instead of failing.
+fn retry<T>(cx: &mut Context<'_>, result: io::Result<T>) -> Poll<Option<io::Result<T>>> {
+    match result {
+        Err(error) if error.kind() == io::ErrorKind::Interrupted => Poll::Ready(None),
+        Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+        result => Poll::Ready(Some(result)),
+    }
+}
+
+pub struct ReadToEnd<'a, R: ?Sized> {
+    reader: &'a mut R,
+    buf: &'a mut Vec<u8>,
+    // length of buf before reading
+    start: usize,
+    site: AwaitSite,
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> ReadToEnd<'_, R> {
+    fn poll_inner(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        loop {
+            let len = self.buf.len();
+            let chunk = (self.buf.capacity() - len).max(MIN_READ_CHUNK);
+            self.buf.resize(len + chunk, 0);
+
+            let read = Pin::new(&mut *self.reader).poll_read(cx, &mut self.buf[len..]);
+            let read = match read {
+                Poll::Ready(result) => retry(cx, result),
+                Poll::Pending => Poll::Pending,
+            };
+            // only keep the bytes that were actually read
+            let n = match &read {
+                Poll::Ready(Some(Ok(n))) => *n,
+                _ => 0,
+            };
+            self.buf.truncate(len + n);
+
+            match ready!(read) {
+                None => continue,
+                Some(Ok(0)) => return Poll::Ready(Ok(self.buf.len() - self.start)),
+                Some(Ok(_)) => continue,
+                Some(Err(error)) => return Poll::Ready(Err(error)),
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> Future for ReadToEnd<'_, R> {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let poll = this.poll_inner(cx);
+        if poll.is_pending() {
+            this.site.pending();
+        }
+        poll
+    }
+}
+
+pub struct ReadExact<'a, R: ?Sized> {
+    reader: &'a mut R,
+    // what's left to fill
+    buf: &'a mut [u8],
+    site: AwaitSite,
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> ReadExact<'_, R> {
+    fn poll_inner(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.buf.is_empty() {
+            let result = ready!(Pin::new(&mut *self.reader).poll_read(cx, self.buf));
+            match ready!(retry(cx, result)) {
+                None => continue,
+                Some(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into())),
+                Some(Ok(n)) => {
+                    let buf = std::mem::take(&mut self.buf);
+                    self.buf = &mut buf[n..];
+                }
+                Some(Err(error)) => return Poll::Ready(Err(error)),
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> Future for ReadExact<'_, R> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let poll = this.poll_inner(cx);
+        if poll.is_pending() {
+            this.site.pending();
+        }
+        poll
+    }
+}
+
+pub struct WriteAll<'a, W: ?Sized> {
+    writer: &'a mut W,
+    // what's left to write
+    buf: &'a [u8],
+    site: AwaitSite,
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> WriteAll<'_, W> {
+    fn poll_inner(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.buf.is_empty() {
+            let result = ready!(Pin::new(&mut *self.writer).poll_write(cx, self.buf));
+            match ready!(retry(cx, result)) {
+                None => continue,
+                Some(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Some(Ok(n)) => self.buf = &self.buf[n..],
+                Some(Err(error)) => return Poll::Ready(Err(error)),
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for WriteAll<'_, W> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let poll = this.poll_inner(cx);
+        if poll.is_pending() {
+            this.site.pending();
+        }
+        poll
+    }
+}
+
+pub struct Close<'a, W: ?Sized> {
+    writer: &'a mut W,
+    site: AwaitSite,
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Close<'_, W> {
+    fn poll_inner(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            let result = ready!(Pin::new(&mut *self.writer).poll_close(cx));
+            if let Some(result) = ready!(retry(cx, result)) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for Close<'_, W> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let poll = this.poll_inner(cx);
+        if poll.is_pending() {
+            this.site.pending();
+        }
+        poll
+    }
+}
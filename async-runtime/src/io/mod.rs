@@ -0,0 +1,7 @@
+//! Async byte streams, built on the `futures::io` read/write traits.
+
+mod duplex;
+mod ext;
+
+pub use duplex::{duplex, DuplexStream};
+pub use ext::{AsyncReadExt, AsyncWriteExt, Close, ReadExact, ReadToEnd, WriteAll};
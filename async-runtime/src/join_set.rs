@@ -0,0 +1,302 @@
+//! Collections of spawned tasks that are joined as a group.
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use futures::future::poll_fn;
+
+use crate::{
+    runtime::{current, rejecting_spawn, AbortHandle, JoinError, JoinHandle},
+    task::{with_wake_source, WakeSource},
+    time::{sleep_until, Sleep},
+};
+
+/// A set of tasks whose results come out in the order they finish.
+///
+/// With `JoinSet::with_batching` the results are handed to the consumer in
+/// batches instead: it's only woken up once `batch_size` results are in, or
+/// once the oldest result has waited for `flush_timeout`, whichever comes
+/// first. This saves wakeups when joining lots of small tasks while keeping
+/// the latency bounded.
+///
+/// Dropping the set leaves the remaining tasks running.
+pub struct JoinSet<R>
+where
+    R: Send + 'static,
+{
+    shared: Arc<Mutex<Results<R>>>,
+    flush_timeout: Duration,
+    // wakes the consumer up when a partial batch is due, with its deadline
+    flush: Option<(Instant, Sleep)>,
+    // results of the last due batch that join_next hasn't returned yet
+    due: usize,
+}
+
+struct Results<R> {
+    // along with when each result came in
+    completed: VecDeque<(Instant, Result<R, JoinError>)>,
+    // tasks that haven't finished yet
+    running: usize,
+    batch_size: usize,
+    waker: Option<Waker>,
+}
+
+impl<R> Results<R> {
+    // a full batch is in, or there's nothing else to wait for
+    fn batch_full(&self) -> bool {
+        self.completed.len() >= self.batch_size || self.running == 0
+    }
+}
+
+// Hands the task's output over to the set, or `Cancelled` if the task is
+// dropped before it finishes, `Draining` if it was never let in, `Lost` if
+// it panicked.
+struct Delivery<R> {
+    shared: Arc<Mutex<Results<R>>>,
+    output: Option<R>,
+}
+
+impl<R> Delivery<R> {
+    fn deliver(mut self, output: R) {
+        self.output = Some(output);
+    }
+}
+
+impl<R> Drop for Delivery<R> {
+    fn drop(&mut self) {
+        let mut results = self.shared.lock().unwrap();
+
+        let output = self.output.take().ok_or_else(|| {
+            if std::thread::panicking() {
+                JoinError::Lost
+            } else if rejecting_spawn() {
+                JoinError::Draining
+            } else {
+                JoinError::Cancelled
+            }
+        });
+        results.completed.push_back((Instant::now(), output));
+        results.running -= 1;
+
+        if results.batch_full() {
+            if let Some(waker) = results.waker.take() {
+                with_wake_source(WakeSource::Channel, || waker.wake());
+            }
+        }
+    }
+}
+
+impl<R> Default for JoinSet<R>
+where
+    R: Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R> JoinSet<R>
+where
+    R: Send + 'static,
+{
+    pub fn new() -> Self {
+        Self::with_batching(1, Duration::ZERO)
+    }
+
+    /// See the type level docs. Panics if `batch_size` is zero.
+    pub fn with_batching(batch_size: usize, flush_timeout: Duration) -> Self {
+        assert!(batch_size > 0, "batch size must be greater than zero");
+
+        Self {
+            shared: Arc::new(Mutex::new(Results {
+                completed: VecDeque::new(),
+                running: 0,
+                batch_size,
+                waker: None,
+            })),
+            flush_timeout,
+            flush: None,
+            due: 0,
+        }
+    }
+
+    /// Spawns `future` on the current runtime. The returned handle aborts
+    /// the task, which then comes out of the set as `JoinError::Cancelled`.
+    /// In drain mode the task comes out as `JoinError::Draining` instead,
+    /// without running, and a task that panics comes out as
+    /// `JoinError::Lost`.
+    pub fn spawn<F>(&mut self, future: F) -> AbortHandle
+    where
+        F: Future<Output = R> + Send + 'static,
+    {
+        self.shared.lock().unwrap().running += 1;
+
+        let delivery = Delivery {
+            shared: self.shared.clone(),
+            output: None,
+        };
+
+        current()
+            .spawn(async move { delivery.deliver(future.await) })
+            .abort_handle()
+            .expect("async tasks can be aborted")
+    }
+
+    /// Waits for the next result, or returns `None` if the set is empty.
+    /// With batching, this waits for a batch to be due and then returns its
+    /// results one call at a time.
+    pub async fn join_next(&mut self) -> Option<Result<R, JoinError>> {
+        if self.due == 0 {
+            self.due = poll_fn(|cx| self.poll_batch(cx)).await;
+        }
+        if self.due == 0 {
+            return None;
+        }
+
+        self.due -= 1;
+        let (_, output) = self.shared.lock().unwrap().completed.pop_front()?;
+        Some(output)
+    }
+
+    /// Waits for a batch to be due and returns all the results that are
+    /// in, which may be more than the batch size. Returns an empty `Vec` if
+    /// the set is empty.
+    pub async fn join_next_batch(&mut self) -> Vec<Result<R, JoinError>> {
+        if self.due == 0 {
+            poll_fn(|cx| self.poll_batch(cx)).await;
+        }
+
+        self.due = 0;
+        let mut results = self.shared.lock().unwrap();
+        results
+            .completed
+            .drain(..)
+            .map(|(_, output)| output)
+            .collect()
+    }
+
+    /// Number of tasks in the set, finished or not, that haven't been
+    /// joined yet.
+    pub fn len(&self) -> usize {
+        let results = self.shared.lock().unwrap();
+        results.running + results.completed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // resolves to the number of results due, zero only if the set is empty
+    fn poll_batch(&mut self, cx: &mut Context<'_>) -> Poll<usize> {
+        let mut results = self.shared.lock().unwrap();
+
+        let oldest = results.completed.front().map(|(at, _)| *at);
+        let timed_out = oldest.is_some_and(|at| at.elapsed() >= self.flush_timeout);
+        if results.batch_full() || timed_out {
+            self.flush = None;
+            return Poll::Ready(results.completed.len());
+        }
+
+        results.waker = Some(cx.waker().clone());
+        drop(results);
+
+        let Some(oldest) = oldest else {
+            return Poll::Pending;
+        };
+
+        // a partial batch is in, make sure it's flushed in time
+        let deadline = oldest + self.flush_timeout;
+        let (_, flush) = match &mut self.flush {
+            Some((at, _)) if *at == deadline => self.flush.as_mut().unwrap(),
+            flush => flush.insert((deadline, sleep_until(deadline))),
+        };
+        match Pin::new(flush).poll(cx) {
+            Poll::Ready(()) => {
+                self.flush = None;
+                Poll::Ready(self.shared.lock().unwrap().completed.len())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A set of tasks whose results come out in the order the tasks were
+/// spawned, no matter the order they finish in. Handy to parallelize the
+/// stages of a pipeline while keeping its output in order.
+///
+/// Results of tasks that finish before their turn are held until the tasks
+/// spawned earlier are joined. To keep that bounded when an early task is
+/// slow, at most `capacity` tasks can be in the set at once.
+pub struct OrderedJoinSet<R>
+where
+    R: Send + 'static,
+{
+    // in spawn order
+    tasks: VecDeque<JoinHandle<R>>,
+    capacity: usize,
+}
+
+impl<R> OrderedJoinSet<R>
+where
+    R: Send + 'static,
+{
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be greater than zero");
+
+        Self {
+            tasks: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Spawns `future` on the current runtime. When the set is full the
+    /// future is handed back untouched, join the next task to make room.
+    pub fn spawn<F>(&mut self, future: F) -> Result<(), F>
+    where
+        F: Future<Output = R> + Send + 'static,
+    {
+        if self.is_full() {
+            return Err(future);
+        }
+
+        self.tasks.push_back(current().spawn(future));
+        Ok(())
+    }
+
+    /// Waits for the earliest spawned task still in the set and returns its
+    /// result, or `None` if the set is empty.
+    ///
+    /// Cancel safe: if the returned future is dropped before it completes,
+    /// the task stays in the set.
+    pub async fn join_next(&mut self) -> Option<Result<R, JoinError>> {
+        let output = self.tasks.front_mut()?.await;
+        self.tasks.pop_front();
+        Some(output)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.tasks.len() == self.capacity
+    }
+
+    /// Aborts every task in the set, see `JoinHandle::abort`. Their results
+    /// still come out of `join_next`, most likely as `JoinError::Cancelled`.
+    pub fn abort_all(&self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
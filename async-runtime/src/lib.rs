@@ -1,2 +1,21 @@
+pub mod autoscale;
+pub mod codec;
+pub mod future;
+pub mod health;
+pub mod heartbeat;
+pub mod io;
+pub mod join_set;
+#[doc(hidden)]
+pub mod macros;
+pub mod metrics;
+mod rate_limit;
+#[cfg(feature = "task-registry")]
+pub mod registry;
 pub mod runtime;
+pub mod stream;
+pub mod sync;
+pub mod task;
+mod tests;
 mod threadpool;
+pub mod time;
+mod util;
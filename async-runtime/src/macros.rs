@@ -0,0 +1,314 @@
+/// Waits on several futures at once and runs the branch of the first one to
+/// complete, like `futures::select!`.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use async_runtime::{select, stream::StreamExt, time::interval};
+/// use futures::{channel::mpsc, FutureExt};
+///
+/// async fn batch(mut messages: mpsc::UnboundedReceiver<String>) {
+///     let mut ticks = interval(Duration::from_secs(1));
+///     let mut batch = Vec::new();
+///     loop {
+///         select! {
+///             message = messages.next() => match message {
+///                 Some(message) => batch.push(message),
+///                 None => break,
+///             },
+///             _ = ticks.tick().fuse() => batch.clear(),
+///         }
+///     }
+/// }
+/// ```
+///
+/// Each branch is `pattern = future => body`. Branch futures must implement
+/// `FusedFuture` so that a future that already finished, e.g. in a previous
+/// iteration of a loop, is skipped instead of being polled again. Futures
+/// that aren't `Unpin` are pinned on the stack.
+///
+/// - If the output of a future doesn't match its pattern, the branch is
+///   disabled and the other branches keep being polled.
+/// - `else => body` runs when none of the branches is ready right away,
+///   instead of waiting.
+/// - `complete => body` runs when every branch is disabled or terminated.
+///   Without it, `select!` panics in that case.
+///
+/// Branches are polled starting from a random one so that a branch that is
+/// always ready can't starve the others. Start with `biased;` to poll them in
+/// the order they're written instead.
+#[macro_export]
+macro_rules! select {
+    (biased; $($tokens:tt)*) => {
+        $crate::__select!(
+            @parse true; []; []; [];
+            [_0 _1 _2 _3 _4 _5 _6 _7 _8 _9 _10 _11 _12 _13 _14 _15 _16 _17 _18 _19];
+            $($tokens)*
+        )
+    };
+    ($($tokens:tt)*) => {
+        $crate::__select!(
+            @parse false; []; []; [];
+            [_0 _1 _2 _3 _4 _5 _6 _7 _8 _9 _10 _11 _12 _13 _14 _15 _16 _17 _18 _19];
+            $($tokens)*
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __select {
+    // every branch has been parsed
+    (@parse $biased:tt; $branches:tt; $else:tt; $complete:tt; [$($vars:ident)*];) => {
+        $crate::__select!(@expand $biased; $branches; $else; $complete)
+    };
+
+    // the comma after a block body is optional
+    (@parse $biased:tt; $branches:tt; $else:tt; $complete:tt; $vars:tt; , $($rest:tt)*) => {
+        $crate::__select!(@parse $biased; $branches; $else; $complete; $vars; $($rest)*)
+    };
+
+    (@parse $biased:tt; $branches:tt; []; $complete:tt; $vars:tt;
+        else => $body:block $($rest:tt)*) => {
+        $crate::__select!(@parse $biased; $branches; [$body]; $complete; $vars; $($rest)*)
+    };
+    (@parse $biased:tt; $branches:tt; []; $complete:tt; $vars:tt;
+        else => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::__select!(@parse $biased; $branches; [{ $body }]; $complete; $vars; $($($rest)*)?)
+    };
+
+    (@parse $biased:tt; $branches:tt; $else:tt; []; $vars:tt;
+        complete => $body:block $($rest:tt)*) => {
+        $crate::__select!(@parse $biased; $branches; $else; [$body]; $vars; $($rest)*)
+    };
+    (@parse $biased:tt; $branches:tt; $else:tt; []; $vars:tt;
+        complete => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::__select!(@parse $biased; $branches; $else; [{ $body }]; $vars; $($($rest)*)?)
+    };
+
+    (@parse $biased:tt; [$($branches:tt)*]; $else:tt; $complete:tt; [$var:ident $($vars:ident)*];
+        $pat:pat = $future:expr => $body:block $($rest:tt)*) => {
+        $crate::__select!(
+            @parse $biased; [$($branches)* ($var, $pat, $future, $body)]; $else; $complete; [$($vars)*];
+            $($rest)*
+        )
+    };
+    (@parse $biased:tt; [$($branches:tt)*]; $else:tt; $complete:tt; [$var:ident $($vars:ident)*];
+        $pat:pat = $future:expr => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::__select!(
+            @parse $biased; [$($branches)* ($var, $pat, $future, { $body })]; $else; $complete; [$($vars)*];
+            $($($rest)*)?
+        )
+    };
+
+    (@expand $biased:tt; [$(($var:ident, $pat:pat, $future:expr, $body:block))+]; $else:tt; $complete:tt) => {{
+        use $crate::macros::__private::{FusedFuture, Future, Poll};
+
+        // the output of whichever branch won, the bodies run outside of the
+        // poll function so that they can await, break or return
+        #[allow(non_camel_case_types, dead_code)]
+        enum __Output<$($var,)+> {
+            $($var($var),)+
+            Complete,
+            Else,
+        }
+
+        $(
+            let mut $var = $crate::macros::__private::pin!($future);
+        )+
+
+        let branches = [$(stringify!($var)),+].len();
+        let start = if $biased {
+            0
+        } else {
+            $crate::macros::__private::random(branches)
+        };
+
+        let output = $crate::macros::__private::poll_fn(|cx| {
+            let mut active = false;
+
+            for i in 0..branches {
+                let branch = (start + i) % branches;
+                let mut index = 0;
+                $(
+                    if index == branch && !FusedFuture::is_terminated(&$var) {
+                        active = true;
+                        if let Poll::Ready(value) = Future::poll($var.as_mut(), cx) {
+                            #[allow(unused_variables, irrefutable_let_patterns)]
+                            if let $pat = &value {
+                                return Poll::Ready(__Output::$var(value));
+                            }
+                            // the pattern didn't match, the branch is now
+                            // disabled since its future is terminated
+                        }
+                    }
+                    index += 1;
+                )+
+                let _ = index;
+            }
+
+            if !active {
+                return Poll::Ready(__Output::Complete);
+            }
+
+            $crate::__select!(@pending $else)
+        })
+        .await;
+
+        #[allow(unreachable_patterns, unused_variables)]
+        let result = match output {
+            $(__Output::$var($pat) => $body,)+
+            __Output::Complete => $crate::__select!(@complete $complete),
+            __Output::Else => $crate::__select!(@else $else),
+            _ => unreachable!("the pattern was checked in the poll function"),
+        };
+        result
+    }};
+
+    (@pending []) => {
+        Poll::Pending
+    };
+    (@pending [$body:block]) => {
+        Poll::Ready(__Output::Else)
+    };
+
+    (@complete []) => {
+        panic!("all branches of select! are disabled and there is no `complete` branch")
+    };
+    (@complete [$body:block]) => {
+        $body
+    };
+
+    (@else []) => {
+        unreachable!("there is no `else` branch")
+    };
+    (@else [$body:block]) => {
+        $body
+    };
+}
+
+/// Awaits several futures returning `Result` at once, like `futures::try_join!`.
+/// Resolves to `Ok` with the tuple of their values once all of them
+/// succeeded, or to the first `Err` as soon as any of them fails.
+///
+/// ```no_run
+/// use async_runtime::try_join;
+///
+/// # struct User;
+/// # struct Order;
+/// # async fn fetch_user(id: u64) -> std::io::Result<User> { Ok(User) }
+/// # async fn fetch_orders(id: u64) -> std::io::Result<Vec<Order>> { Ok(Vec::new()) }
+/// async fn profile(id: u64) -> std::io::Result<(User, Vec<Order>)> {
+///     let (user, orders) = try_join!(fetch_user(id), fetch_orders(id))?;
+///     Ok((user, orders))
+/// }
+/// ```
+///
+/// Every branch that hasn't finished yet is polled on each wake, in the
+/// order they're written. The error types of the branches must be the same.
+/// On the first error the other branches are dropped, which cancels the
+/// ones still running.
+#[macro_export]
+macro_rules! try_join {
+    ($($future:expr),+ $(,)?) => {
+        $crate::__try_join!(
+            @parse [];
+            [_0 _1 _2 _3 _4 _5 _6 _7 _8 _9 _10 _11 _12 _13 _14 _15 _16 _17 _18 _19];
+            $($future,)+
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __try_join {
+    (@parse [$($branches:tt)*]; [$var:ident $($vars:ident)*]; $future:expr, $($rest:tt)*) => {
+        $crate::__try_join!(@parse [$($branches)* ($var, $future)]; [$($vars)*]; $($rest)*)
+    };
+
+    // every branch has been parsed
+    (@parse [$(($var:ident, $future:expr))+]; [$($vars:ident)*];) => {{
+        use $crate::macros::__private::{Future, Poll};
+
+        $(
+            let mut $var = $crate::macros::__private::pin!(
+                $crate::macros::__private::maybe_done($future)
+            );
+        )+
+
+        // the futures are pinned in this block so they're dropped as soon
+        // as it ends, an error doesn't wait for the others to finish
+        $crate::macros::__private::poll_fn(|cx| {
+            let mut done = true;
+
+            $(
+                // a finished branch keeps its output and is ready right away
+                if Future::poll($var.as_mut(), cx).is_pending() {
+                    done = false;
+                } else if $var.as_mut().output_mut().map_or(false, |output| output.is_err()) {
+                    match $var.as_mut().take_output() {
+                        Some(Err(error)) => return Poll::Ready(Err(error)),
+                        _ => unreachable!("the output was checked to be an error"),
+                    }
+                }
+            )+
+
+            if !done {
+                return Poll::Pending;
+            }
+
+            Poll::Ready(Ok(($(
+                match $var.as_mut().take_output() {
+                    Some(Ok(value)) => value,
+                    _ => unreachable!("every branch completed successfully"),
+                },
+            )+)))
+        })
+        .await
+    }};
+}
+
+/// Declares values with one instance per worker thread, see
+/// `runtime::WorkerLocal`.
+///
+/// ```no_run
+/// use std::cell::RefCell;
+///
+/// use async_runtime::worker_local;
+///
+/// worker_local! {
+///     static BUFFER: RefCell<Vec<u8>> = RefCell::new(Vec::with_capacity(4096));
+/// }
+///
+/// // from a task on one of the workers
+/// BUFFER.with(|buffer| buffer.borrow_mut().clear());
+/// ```
+#[macro_export]
+macro_rules! worker_local {
+    () => {};
+
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $(#[$attr])*
+        $vis static $name: $crate::runtime::WorkerLocal<$t> = {
+            ::std::thread_local! {
+                static INNER: $t = $init;
+            }
+            $crate::runtime::WorkerLocal::new(&INNER)
+        };
+
+        $crate::worker_local!($($rest)*);
+    };
+
+    // the last one may omit the semicolon
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $crate::worker_local!($(#[$attr])* $vis static $name: $t = $init;);
+    };
+}
+
+#[doc(hidden)]
+pub mod __private {
+    pub use futures::future::{maybe_done, poll_fn, FusedFuture, Future};
+    pub use std::{pin::pin, task::Poll};
+
+    pub use crate::util::random;
+}
@@ -0,0 +1,688 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// how long the spawn rate is averaged over, at least
+const SPAWN_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// bucket i of the scheduling delay histogram holds delays up to 2^i µs, the
+// last one everything longer than about 4s
+const SCHEDULING_DELAY_BUCKETS: usize = 24;
+
+/// Counters shared by the runtime handle and its workers.
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    live_workers: AtomicUsize,
+    parked_workers: AtomicUsize,
+    failed_steals: AtomicU64,
+    workers_scaled_up: AtomicU64,
+    workers_scaled_down: AtomicU64,
+    spawned_tasks: AtomicU64,
+    spawn_rate: Mutex<RateSample>,
+    // time tasks spent queued before a worker picked them up
+    scheduled_polls: AtomicU64,
+    scheduling_delay_nanos: AtomicU64,
+    max_scheduling_delay_nanos: AtomicU64,
+    scheduling_delay_buckets: [AtomicU64; SCHEDULING_DELAY_BUCKETS],
+    // completed in time and elapsed counts, by label
+    timeouts: Mutex<BTreeMap<Option<&'static str>, (u64, u64)>>,
+    #[cfg(feature = "memory-accounting")]
+    live_task_bytes: AtomicUsize,
+    #[cfg(feature = "memory-accounting")]
+    queued_task_bytes: AtomicUsize,
+    // indexed by worker
+    workers: Box<[WorkerCounters]>,
+}
+
+/// The spawn rate as of the last time it was sampled.
+#[derive(Debug)]
+struct RateSample {
+    at: Instant,
+    spawned_tasks: u64,
+    per_second: f64,
+}
+
+#[derive(Debug, Default)]
+struct WorkerCounters {
+    busy_nanos: AtomicU64,
+    cpu_nanos: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn new(num_workers: usize) -> Self {
+        Self {
+            live_workers: AtomicUsize::new(0),
+            parked_workers: AtomicUsize::new(0),
+            failed_steals: AtomicU64::new(0),
+            workers_scaled_up: AtomicU64::new(0),
+            workers_scaled_down: AtomicU64::new(0),
+            spawned_tasks: AtomicU64::new(0),
+            spawn_rate: Mutex::new(RateSample {
+                at: Instant::now(),
+                spawned_tasks: 0,
+                per_second: 0.0,
+            }),
+            scheduled_polls: AtomicU64::new(0),
+            scheduling_delay_nanos: AtomicU64::new(0),
+            max_scheduling_delay_nanos: AtomicU64::new(0),
+            scheduling_delay_buckets: Default::default(),
+            timeouts: Mutex::new(BTreeMap::new()),
+            #[cfg(feature = "memory-accounting")]
+            live_task_bytes: AtomicUsize::new(0),
+            #[cfg(feature = "memory-accounting")]
+            queued_task_bytes: AtomicUsize::new(0),
+            workers: (0..num_workers).map(|_| Default::default()).collect(),
+        }
+    }
+
+    pub(crate) fn worker_started(&self) {
+        self.live_workers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn worker_stopped(&self) {
+        self.live_workers.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn worker_parked(&self) {
+        self.parked_workers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn worker_unparked(&self) {
+        self.parked_workers.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // the two counters are read separately so they may be off by the odd
+    // worker that's just parking or waking up
+    pub(crate) fn active_workers(&self) -> usize {
+        let live = self.live_workers.load(Ordering::Relaxed);
+        live.saturating_sub(self.parked_workers.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn parked_workers(&self) -> usize {
+        self.parked_workers.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn workers_scaled_up(&self) {
+        self.workers_scaled_up.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn workers_scaled_down(&self) {
+        self.workers_scaled_down.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn failed_steal(&self) {
+        self.failed_steals.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn task_spawned(&self, #[cfg(feature = "memory-accounting")] size: usize) {
+        self.spawned_tasks.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "memory-accounting")]
+        {
+            self.live_task_bytes.fetch_add(size, Ordering::Relaxed);
+            self.queued_task_bytes.fetch_add(size, Ordering::Relaxed);
+        }
+    }
+
+    /// Accounts for a task picked up by a worker for the first time.
+    #[cfg(feature = "memory-accounting")]
+    pub(crate) fn task_started(&self, size: usize) {
+        self.queued_task_bytes.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "memory-accounting")]
+    pub(crate) fn queued_task_bytes(&self) -> usize {
+        self.queued_task_bytes.load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "memory-accounting")]
+    pub(crate) fn task_completed(&self, size: usize) {
+        self.live_task_bytes.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    /// Accounts for a task picked up from a queue after waiting there for
+    /// `delay`.
+    pub(crate) fn task_dequeued(&self, delay: Duration) {
+        let nanos = delay.as_nanos() as u64;
+        self.scheduled_polls.fetch_add(1, Ordering::Relaxed);
+        self.scheduling_delay_nanos
+            .fetch_add(nanos, Ordering::Relaxed);
+        self.max_scheduling_delay_nanos
+            .fetch_max(nanos, Ordering::Relaxed);
+        self.scheduling_delay_buckets[scheduling_delay_bucket(nanos)]
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Accounts for one poll on worker `index`, with the CPU time spent by the
+    /// worker thread if the platform could tell.
+    pub(crate) fn task_polled(&self, index: usize, busy: Duration, cpu: Option<Duration>) {
+        let worker = &self.workers[index];
+        worker
+            .busy_nanos
+            .fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+        if let Some(cpu) = cpu {
+            worker
+                .cpu_nanos
+                .fetch_add(cpu.as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn timeout_resolved(&self, label: Option<&'static str>, elapsed: bool) {
+        let mut timeouts = self.timeouts.lock().unwrap();
+        let (completed, elapsed_count) = timeouts.entry(label).or_default();
+        if elapsed {
+            *elapsed_count += 1;
+        } else {
+            *completed += 1;
+        }
+    }
+
+    // spawns per second over the last window, refreshed once the current
+    // window is over
+    fn spawn_rate(&self, spawned_tasks: u64) -> f64 {
+        let mut sample = self.spawn_rate.lock().unwrap();
+
+        let elapsed = sample.at.elapsed();
+        if elapsed >= SPAWN_RATE_WINDOW {
+            sample.per_second =
+                (spawned_tasks - sample.spawned_tasks) as f64 / elapsed.as_secs_f64();
+            sample.at = Instant::now();
+            sample.spawned_tasks = spawned_tasks;
+        }
+
+        sample.per_second
+    }
+
+    pub(crate) fn snapshot(
+        &self,
+        global_queue_depth: usize,
+        blocking_threads: usize,
+        overflow_blocking_threads: usize,
+        blocking_pools: Vec<BlockingPoolMetrics>,
+    ) -> RuntimeMetrics {
+        let spawned_tasks = self.spawned_tasks.load(Ordering::Relaxed);
+        let scheduled_polls = self.scheduled_polls.load(Ordering::Relaxed);
+        let scheduling_delay_nanos = self.scheduling_delay_nanos.load(Ordering::Relaxed);
+
+        RuntimeMetrics {
+            live_workers: self.live_workers.load(Ordering::Relaxed),
+            global_queue_depth,
+            parked_workers: self.parked_workers.load(Ordering::Relaxed),
+            failed_steals: self.failed_steals.load(Ordering::Relaxed),
+            workers_scaled_up: self.workers_scaled_up.load(Ordering::Relaxed),
+            workers_scaled_down: self.workers_scaled_down.load(Ordering::Relaxed),
+            spawned_tasks,
+            spawn_rate: self.spawn_rate(spawned_tasks),
+            blocking_threads,
+            overflow_blocking_threads,
+            blocking_pools,
+            scheduled_polls,
+            mean_scheduling_delay: Duration::from_nanos(
+                scheduling_delay_nanos
+                    .checked_div(scheduled_polls)
+                    .unwrap_or_default(),
+            ),
+            max_scheduling_delay: Duration::from_nanos(
+                self.max_scheduling_delay_nanos.load(Ordering::Relaxed),
+            ),
+            total_scheduling_delay: Duration::from_nanos(scheduling_delay_nanos),
+            scheduling_delays: self
+                .scheduling_delay_buckets
+                .iter()
+                .enumerate()
+                .map(|(index, count)| HistogramBucket {
+                    upper_bound: (index + 1 < SCHEDULING_DELAY_BUCKETS)
+                        .then(|| Duration::from_micros(1 << index)),
+                    count: count.load(Ordering::Relaxed),
+                })
+                .collect(),
+            timeouts: self
+                .timeouts
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(label, (completed, elapsed))| TimeoutMetrics {
+                    label: *label,
+                    completed: *completed,
+                    elapsed: *elapsed,
+                })
+                .collect(),
+            #[cfg(feature = "memory-accounting")]
+            live_task_bytes: self.live_task_bytes.load(Ordering::Relaxed),
+            #[cfg(feature = "memory-accounting")]
+            queued_task_bytes: self.queued_task_bytes(),
+            workers: self
+                .workers
+                .iter()
+                .map(|worker| WorkerMetrics {
+                    busy_time: Duration::from_nanos(worker.busy_nanos.load(Ordering::Relaxed)),
+                    cpu_time: crate::util::THREAD_CPU_TIME_SUPPORTED
+                        .then(|| Duration::from_nanos(worker.cpu_nanos.load(Ordering::Relaxed))),
+                })
+                .collect(),
+        }
+    }
+}
+
+// the smallest i with nanos <= 2^i µs, capped at the last bucket
+fn scheduling_delay_bucket(nanos: u64) -> usize {
+    let micros = nanos.div_ceil(1000);
+    let index = (u64::BITS - micros.saturating_sub(1).leading_zeros()) as usize;
+    index.min(SCHEDULING_DELAY_BUCKETS - 1)
+}
+
+/// A point-in-time copy of the runtime metrics.
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeMetrics {
+    /// Number of worker threads that are running. It drops below the
+    /// configured worker count when a worker dies from a panicking task.
+    pub live_workers: usize,
+
+    /// Number of tasks waiting in the global queue to be polled.
+    pub global_queue_depth: usize,
+
+    /// Number of workers blocked waiting for a task to show up.
+    pub parked_workers: usize,
+
+    /// Number of times an idle worker looked for a task in the queues and
+    /// came back empty handed, before backing off or parking. A high rate
+    /// with many parked workers means the backoff is too aggressive.
+    pub failed_steals: u64,
+
+    /// Number of times the auto-scaler put a worker in or took one out of
+    /// rotation, see `Builder::auto_scale`.
+    pub workers_scaled_up: u64,
+    pub workers_scaled_down: u64,
+
+    /// Number of tasks spawned since the runtime started.
+    pub spawned_tasks: u64,
+
+    /// Spawns per second, averaged over a window of at least a second. The
+    /// window moves forward when the metrics are read, so with infrequent
+    /// reads it covers the time since the previous read.
+    pub spawn_rate: f64,
+
+    /// Number of threads in the blocking pool. The worker threads run on
+    /// the pool too and are counted here.
+    pub blocking_threads: usize,
+
+    /// How many of `blocking_threads` were started beyond the size of the
+    /// pool to drain a backlog, see `Builder::blocking_overflow`.
+    pub overflow_blocking_threads: usize,
+
+    /// The named blocking pools, see `Handle::blocking_pool`, sorted by
+    /// name. Their threads aren't counted in `blocking_threads`.
+    pub blocking_pools: Vec<BlockingPoolMetrics>,
+
+    /// Number of times a worker picked a task up from a queue, after a
+    /// spawn or a wake, since the runtime started.
+    pub scheduled_polls: u64,
+
+    /// How long a task waited in a queue before being polled, on average
+    /// over `scheduled_polls`. A high delay while workers are parked points
+    /// at a lost wakeup, with no parked workers at too few workers.
+    pub mean_scheduling_delay: Duration,
+
+    /// The longest a task ever waited in a queue before being polled.
+    pub max_scheduling_delay: Duration,
+
+    /// How long tasks waited in a queue before being polled, summed over
+    /// `scheduled_polls`.
+    pub total_scheduling_delay: Duration,
+
+    /// How long tasks waited in a queue before being polled, as a histogram
+    /// over `scheduled_polls` with power of two bounds from 1µs to about 4s,
+    /// ordered by bound. Tail latency the mean hides shows up in the higher
+    /// buckets.
+    pub scheduling_delays: Vec<HistogramBucket>,
+
+    /// How `time::timeout`s resolved, one entry per label with the unlabeled
+    /// ones first.
+    pub timeouts: Vec<TimeoutMetrics>,
+
+    /// Total size in bytes of the futures held by tasks that haven't finished
+    /// yet. This is an approximation: it's the `size_of_val` of each boxed
+    /// future, so heap allocations owned by the future are not counted.
+    /// Unusually large values usually point at bloated async state machines.
+    #[cfg(feature = "memory-accounting")]
+    pub live_task_bytes: usize,
+
+    /// The part of `live_task_bytes` held by tasks that no worker has picked
+    /// up yet, capped by `Builder::max_queued_task_bytes`.
+    #[cfg(feature = "memory-accounting")]
+    pub queued_task_bytes: usize,
+
+    /// Per worker figures, indexed like `Handle::drain_worker`.
+    pub workers: Vec<WorkerMetrics>,
+}
+
+impl RuntimeMetrics {
+    /// Serializes the metrics in the Prometheus text exposition format, to
+    /// be served as is from a `/metrics` endpoint. The names are stable:
+    ///
+    /// - gauges `async_runtime_live_workers`, `async_runtime_parked_workers`,
+    ///   `async_runtime_global_queue_depth`, `async_runtime_spawn_rate`,
+    ///   `async_runtime_blocking_threads`,
+    ///   `async_runtime_overflow_blocking_threads`,
+    ///   `async_runtime_mean_scheduling_delay_seconds`,
+    ///   `async_runtime_max_scheduling_delay_seconds` and, with the
+    ///   `memory-accounting` feature, `async_runtime_live_task_bytes` and
+    ///   `async_runtime_queued_task_bytes`
+    /// - counters `async_runtime_failed_steals_total`,
+    ///   `async_runtime_workers_scaled_up_total`,
+    ///   `async_runtime_workers_scaled_down_total`,
+    ///   `async_runtime_spawned_tasks_total` and
+    ///   `async_runtime_scheduled_polls_total`
+    /// - histogram `async_runtime_scheduling_delay_seconds`, with the
+    ///   buckets of `scheduling_delays`
+    /// - per named blocking pool, labeled `pool`: gauges
+    ///   `async_runtime_blocking_pool_threads`,
+    ///   `async_runtime_blocking_pool_busy_threads` and
+    ///   `async_runtime_blocking_pool_queue_depth`
+    /// - per timeout label, labeled `label` (empty for the unlabeled ones)
+    ///   and `outcome` (`completed` or `elapsed`): counter
+    ///   `async_runtime_timeouts_total`
+    /// - per worker, labeled `worker` with its index: counters
+    ///   `async_runtime_worker_busy_seconds_total` and, where it's measured,
+    ///   `async_runtime_worker_cpu_seconds_total`
+    pub fn to_prometheus(&self) -> String {
+        let mut out = Exposition::default();
+
+        out.metric("live_workers", "gauge", "Worker threads running.");
+        out.sample("live_workers", &[], self.live_workers);
+        out.metric("parked_workers", "gauge", "Workers waiting for a task.");
+        out.sample("parked_workers", &[], self.parked_workers);
+        out.metric(
+            "global_queue_depth",
+            "gauge",
+            "Tasks waiting in the global queue.",
+        );
+        out.sample("global_queue_depth", &[], self.global_queue_depth);
+        out.metric(
+            "failed_steals_total",
+            "counter",
+            "Times an idle worker found no task.",
+        );
+        out.sample("failed_steals_total", &[], self.failed_steals);
+        out.metric(
+            "workers_scaled_up_total",
+            "counter",
+            "Workers put in rotation by the auto-scaler.",
+        );
+        out.sample("workers_scaled_up_total", &[], self.workers_scaled_up);
+        out.metric(
+            "workers_scaled_down_total",
+            "counter",
+            "Workers taken out of rotation by the auto-scaler.",
+        );
+        out.sample("workers_scaled_down_total", &[], self.workers_scaled_down);
+        out.metric("spawned_tasks_total", "counter", "Tasks spawned.");
+        out.sample("spawned_tasks_total", &[], self.spawned_tasks);
+        out.metric("spawn_rate", "gauge", "Tasks spawned per second.");
+        out.sample("spawn_rate", &[], self.spawn_rate);
+        out.metric(
+            "blocking_threads",
+            "gauge",
+            "Threads of the blocking pool, workers included.",
+        );
+        out.sample("blocking_threads", &[], self.blocking_threads);
+        out.metric(
+            "overflow_blocking_threads",
+            "gauge",
+            "Blocking threads started beyond the pool size.",
+        );
+        out.sample(
+            "overflow_blocking_threads",
+            &[],
+            self.overflow_blocking_threads,
+        );
+
+        out.metric(
+            "blocking_pool_threads",
+            "gauge",
+            "Threads of a named blocking pool.",
+        );
+        for pool in &self.blocking_pools {
+            out.sample(
+                "blocking_pool_threads",
+                &[("pool", &pool.name)],
+                pool.threads,
+            );
+        }
+        out.metric(
+            "blocking_pool_busy_threads",
+            "gauge",
+            "Threads of a named blocking pool running a task.",
+        );
+        for pool in &self.blocking_pools {
+            out.sample(
+                "blocking_pool_busy_threads",
+                &[("pool", &pool.name)],
+                pool.busy_threads,
+            );
+        }
+        out.metric(
+            "blocking_pool_queue_depth",
+            "gauge",
+            "Tasks waiting for a thread of a named blocking pool.",
+        );
+        for pool in &self.blocking_pools {
+            out.sample(
+                "blocking_pool_queue_depth",
+                &[("pool", &pool.name)],
+                pool.queue_depth,
+            );
+        }
+
+        out.metric(
+            "scheduled_polls_total",
+            "counter",
+            "Tasks picked up from a queue.",
+        );
+        out.sample("scheduled_polls_total", &[], self.scheduled_polls);
+        out.metric(
+            "mean_scheduling_delay_seconds",
+            "gauge",
+            "Mean time tasks waited in a queue.",
+        );
+        out.sample(
+            "mean_scheduling_delay_seconds",
+            &[],
+            self.mean_scheduling_delay.as_secs_f64(),
+        );
+        out.metric(
+            "max_scheduling_delay_seconds",
+            "gauge",
+            "Longest time a task waited in a queue.",
+        );
+        out.sample(
+            "max_scheduling_delay_seconds",
+            &[],
+            self.max_scheduling_delay.as_secs_f64(),
+        );
+
+        out.metric(
+            "scheduling_delay_seconds",
+            "histogram",
+            "Time tasks waited in a queue.",
+        );
+        out.histogram(
+            "scheduling_delay_seconds",
+            &self.scheduling_delays,
+            self.total_scheduling_delay,
+        );
+
+        out.metric(
+            "timeouts_total",
+            "counter",
+            "Timeouts by label and outcome.",
+        );
+        for timeout in &self.timeouts {
+            let label = timeout.label.unwrap_or_default();
+            out.sample(
+                "timeouts_total",
+                &[("label", label), ("outcome", "completed")],
+                timeout.completed,
+            );
+            out.sample(
+                "timeouts_total",
+                &[("label", label), ("outcome", "elapsed")],
+                timeout.elapsed,
+            );
+        }
+
+        #[cfg(feature = "memory-accounting")]
+        {
+            out.metric(
+                "live_task_bytes",
+                "gauge",
+                "Size of the futures of the live tasks.",
+            );
+            out.sample("live_task_bytes", &[], self.live_task_bytes);
+            out.metric(
+                "queued_task_bytes",
+                "gauge",
+                "Size of the futures of the tasks not started yet.",
+            );
+            out.sample("queued_task_bytes", &[], self.queued_task_bytes);
+        }
+
+        out.metric(
+            "worker_busy_seconds_total",
+            "counter",
+            "Wall-clock time a worker spent polling tasks.",
+        );
+        for (index, worker) in self.workers.iter().enumerate() {
+            let index = index.to_string();
+            out.sample(
+                "worker_busy_seconds_total",
+                &[("worker", &index)],
+                worker.busy_time.as_secs_f64(),
+            );
+        }
+        if crate::util::THREAD_CPU_TIME_SUPPORTED {
+            out.metric(
+                "worker_cpu_seconds_total",
+                "counter",
+                "CPU time a worker spent polling tasks.",
+            );
+            for (index, worker) in self.workers.iter().enumerate() {
+                let index = index.to_string();
+                let cpu_time = worker.cpu_time.unwrap_or_default();
+                out.sample(
+                    "worker_cpu_seconds_total",
+                    &[("worker", &index)],
+                    cpu_time.as_secs_f64(),
+                );
+            }
+        }
+
+        out.text
+    }
+}
+
+/// Builds the text of `RuntimeMetrics::to_prometheus`.
+#[derive(Default)]
+struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    const PREFIX: &'static str = "async_runtime_";
+
+    fn metric(&mut self, name: &str, kind: &str, help: &str) {
+        let prefix = Self::PREFIX;
+        // writing to a String can't fail
+        let _ = writeln!(self.text, "# HELP {prefix}{name} {help}");
+        let _ = writeln!(self.text, "# TYPE {prefix}{name} {kind}");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        let _ = write!(self.text, "{}{name}", Self::PREFIX);
+        for (index, (label, value)) in labels.iter().enumerate() {
+            let separator = if index == 0 { '{' } else { ',' };
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            let _ = write!(self.text, "{separator}{label}=\"{value}\"");
+        }
+        if !labels.is_empty() {
+            self.text.push('}');
+        }
+        let _ = writeln!(self.text, " {value}");
+    }
+
+    // the buckets are cumulative in the exposition, the last one is +Inf
+    fn histogram(&mut self, name: &str, buckets: &[HistogramBucket], sum: Duration) {
+        let bucket_name = format!("{name}_bucket");
+        let mut count = 0;
+        for bucket in buckets {
+            count += bucket.count;
+            let le = match bucket.upper_bound {
+                Some(bound) => bound.as_secs_f64().to_string(),
+                None => "+Inf".to_owned(),
+            };
+            self.sample(&bucket_name, &[("le", &le)], count);
+        }
+        self.sample(&format!("{name}_sum"), &[], sum.as_secs_f64());
+        self.sample(&format!("{name}_count"), &[], count);
+    }
+}
+
+/// A point-in-time copy of the metrics of a named blocking pool.
+#[derive(Debug, Clone, Default)]
+pub struct BlockingPoolMetrics {
+    pub name: String,
+
+    /// Number of threads in the pool, busy or idle.
+    pub threads: usize,
+
+    /// Number of threads running a task.
+    pub busy_threads: usize,
+
+    /// Number of tasks waiting for a thread. Growing while all the threads
+    /// are busy means the pool is too small for its workload.
+    pub queue_depth: usize,
+}
+
+/// A bucket of a histogram, counting the values above the bound of the
+/// previous bucket, if any, and up to its own.
+#[derive(Debug, Clone, Default)]
+pub struct HistogramBucket {
+    /// The largest value counted in the bucket, `None` for the last bucket
+    /// which counts everything above the previous one.
+    pub upper_bound: Option<Duration>,
+
+    pub count: u64,
+}
+
+/// Counts of the timeouts sharing a label, see `time::Timeout::label`.
+#[derive(Debug, Clone, Default)]
+pub struct TimeoutMetrics {
+    pub label: Option<&'static str>,
+
+    /// Number of futures that completed before their deadline.
+    pub completed: u64,
+
+    /// Number of futures that were cut short by their deadline.
+    pub elapsed: u64,
+}
+
+/// A point-in-time copy of the metrics of one worker.
+#[derive(Debug, Clone, Default)]
+pub struct WorkerMetrics {
+    /// Wall-clock time spent polling tasks.
+    pub busy_time: Duration,
+
+    /// CPU time the worker thread spent polling tasks, `None` on platforms
+    /// where it can't be measured (only Linux is supported for now). When
+    /// it's well below `busy_time`, tasks are blocking the worker, e.g. in
+    /// a syscall, instead of computing.
+    pub cpu_time: Option<Duration>,
+}
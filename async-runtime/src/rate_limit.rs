@@ -0,0 +1,48 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Token bucket holding up to a second worth of tokens. Tokens are refilled
+/// lazily from the time elapsed since the last acquisition, callers that
+/// come out empty handed wait on the timer for the next one.
+pub(crate) struct TokenBucket {
+    per_second: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(per_second: u32) -> Self {
+        Self {
+            per_second: per_second.into(),
+            state: Mutex::new(BucketState {
+                tokens: per_second.into(),
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token, or returns how long until the next one is available.
+    pub(crate) fn try_acquire(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+
+        let now = Instant::now();
+        let refill = (now - state.refilled_at).as_secs_f64() * self.per_second;
+        state.tokens = (state.tokens + refill).min(self.per_second);
+        state.refilled_at = now;
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - state.tokens) / self.per_second,
+            ))
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc, Mutex, Weak},
+    time::Instant,
+};
+
+#[cfg(feature = "await-locations")]
+use crate::task::AwaitPoint;
+#[cfg(feature = "wake-sources")]
+use crate::task::WakeCounts;
+use crate::{
+    heartbeat::TaskState,
+    runtime::{Task, TaskId, NO_WORKER},
+};
+
+/// A snapshot of a live task, see `Handle::tasks`.
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    /// Set with `Handle::spawn_named`.
+    pub name: Option<String>,
+    pub spawned_at: Instant,
+    /// How many times the task has been polled.
+    pub polls: u64,
+    pub state: TaskState,
+    /// Index of the worker that polled the task last, `None` if it hasn't
+    /// run yet.
+    pub last_worker: Option<usize>,
+}
+
+/// Debugging figures of a live task, see `Handle::task_stats`.
+#[cfg(any(feature = "wake-sources", feature = "await-locations"))]
+#[derive(Debug, Clone)]
+pub struct TaskStats {
+    pub id: TaskId,
+    /// How many times the task has been polled. Far fewer polls that made
+    /// progress than wakes points at spurious wakeups.
+    pub polls: u64,
+    /// What woke the task, by source.
+    #[cfg(feature = "wake-sources")]
+    pub wakes: WakeCounts,
+    /// The future of the crate the task returned `Poll::Pending` from on
+    /// its last poll. `None` if it hasn't been polled yet, or if it's
+    /// suspended on something else, e.g. a foreign channel.
+    #[cfg(feature = "await-locations")]
+    pub last_await: Option<AwaitPoint>,
+}
+
+/// Where every task and worker of a runtime is at, see `Handle::snapshot`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeSnapshot {
+    /// Tasks waiting in a queue for a worker, the global one or a worker's
+    /// local one, the longest waiting first.
+    pub queued: Vec<TaskId>,
+    /// Tasks waiting to be woken, sorted by id.
+    pub idle: Vec<TaskId>,
+    /// Indexed like `Handle::drain_worker`.
+    pub workers: Vec<WorkerSnapshot>,
+    /// The deadlines registered with the timer, soonest first, rounded up
+    /// to the timer granularity.
+    pub timers: Vec<Instant>,
+}
+
+/// The state of one worker in a `RuntimeSnapshot`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkerSnapshot {
+    /// The task the worker is polling, `None` if it's looking for one or
+    /// parked.
+    pub running: Option<TaskId>,
+    /// Number of tasks in its local queue, see `Handle::spawn_in_group`.
+    /// They're listed in `RuntimeSnapshot::queued` too.
+    pub local_queue_depth: usize,
+    /// See `Handle::drain_worker`.
+    pub drained: bool,
+}
+
+/// Every live task of a runtime. Tasks are inserted on spawn and removed
+/// when they complete or are aborted.
+#[derive(Default)]
+pub(crate) struct Registry {
+    tasks: Mutex<HashMap<TaskId, Weak<Task<'static>>>>,
+}
+
+impl Registry {
+    pub(crate) fn insert(&self, task: &Arc<Task<'static>>) {
+        self.tasks
+            .lock()
+            .unwrap()
+            .insert(task.id, Arc::downgrade(task));
+    }
+
+    pub(crate) fn remove(&self, id: TaskId) {
+        self.tasks.lock().unwrap().remove(&id);
+    }
+
+    pub(crate) fn get(&self, id: TaskId) -> Option<Arc<Task<'static>>> {
+        self.tasks.lock().unwrap().get(&id)?.upgrade()
+    }
+
+    pub(crate) fn last_worker(&self, id: TaskId) -> Option<usize> {
+        let task = self.get(id)?;
+        last_worker(&task)
+    }
+
+    #[cfg(any(feature = "wake-sources", feature = "await-locations"))]
+    pub(crate) fn stats(&self, id: TaskId) -> Option<TaskStats> {
+        let task = self.get(id)?;
+        #[cfg(feature = "await-locations")]
+        let last_await = *task.last_await.lock().unwrap();
+
+        Some(TaskStats {
+            id,
+            polls: task.watch.as_ref().map_or(0, |watch| watch.polls()),
+            #[cfg(feature = "wake-sources")]
+            wakes: task.wakes.snapshot(),
+            #[cfg(feature = "await-locations")]
+            last_await,
+        })
+    }
+
+    pub(crate) fn tasks(&self) -> Vec<TaskInfo> {
+        let tasks = self.tasks.lock().unwrap();
+
+        tasks
+            .values()
+            .filter_map(|task| {
+                let task = task.upgrade()?;
+                let watch = task.watch.as_ref()?;
+                let (state, _) = watch.state()?;
+
+                Some(TaskInfo {
+                    id: task.id,
+                    name: task.name.clone(),
+                    spawned_at: watch.spawned_at,
+                    polls: watch.polls(),
+                    state,
+                    last_worker: last_worker(&task),
+                })
+            })
+            .collect()
+    }
+
+    /// Fills in where the tasks are, on top of the rest of the snapshot.
+    pub(crate) fn snapshot(
+        &self,
+        mut workers: Vec<WorkerSnapshot>,
+        timers: Vec<Instant>,
+    ) -> RuntimeSnapshot {
+        let mut queued = Vec::new();
+        let mut idle = Vec::new();
+
+        for task in self.tasks.lock().unwrap().values() {
+            let Some(task) = task.upgrade() else {
+                continue;
+            };
+            let Some((state, since)) = task.watch.as_ref().and_then(|watch| watch.state()) else {
+                continue;
+            };
+
+            match state {
+                TaskState::Queued => queued.push((since, task.id)),
+                TaskState::Idle => idle.push(task.id),
+                TaskState::Running => {
+                    if let Some(worker) =
+                        last_worker(&task).and_then(|index| workers.get_mut(index))
+                    {
+                        worker.running = Some(task.id);
+                    }
+                }
+            }
+        }
+
+        queued.sort_by(|(a, _), (b, _)| b.cmp(a));
+        idle.sort();
+
+        RuntimeSnapshot {
+            queued: queued.into_iter().map(|(_, id)| id).collect(),
+            idle,
+            workers,
+            timers,
+        }
+    }
+}
+
+fn last_worker(task: &Task<'static>) -> Option<usize> {
+    let worker = task.last_worker.load(Ordering::Relaxed);
+    (worker != NO_WORKER).then_some(worker)
+}
@@ -1,77 +1,878 @@
 // TODO does task really need to be wrapped in Arc?
 //
 use futures::{
+    future::BoxFuture,
     task::{waker_ref, ArcWake},
-    Future,
+    Future, FutureExt,
 };
-use log::debug;
+use log::{debug, error};
 use std::{
-    any::Any,
-    cell::RefCell,
+    cell::{Cell, RefCell},
+    collections::{btree_map::Entry, BTreeMap},
+    fmt::Display,
+    hash::{Hash, Hasher},
+    panic::{self, AssertUnwindSafe},
     pin::Pin,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    task::Waker,
+    time::{Duration, Instant},
 };
 
-use crate::threadpool::{JoinHandle, ThreadPool};
+use crossbeam_utils::Backoff;
+
+use crate::{
+    autoscale::{AutoScaleConfig, AutoScaler},
+    health::{HealthStatus, HealthThresholds, QueueDepthHistory},
+    heartbeat::{HeartbeatCallback, TaskHeartbeat, TaskWatch, Watchdog},
+    metrics::{BlockingPoolMetrics, Metrics, RuntimeMetrics},
+    rate_limit::TokenBucket,
+    sync::Semaphore,
+    task::Extensions,
+    threadpool::{result_channel, ErasedOutput, ResultSender, TaskOutput, ThreadPool},
+    time::{sleep, Timer},
+    util::{random, thread_cpu_time, FnvHasher},
+};
+
+#[cfg(any(feature = "wake-sources", feature = "await-locations"))]
+use crate::registry::TaskStats;
+#[cfg(feature = "task-registry")]
+use crate::registry::{Registry, RuntimeSnapshot, TaskInfo, WorkerSnapshot};
+#[cfg(feature = "await-locations")]
+use crate::task::AwaitPoint;
+#[cfg(feature = "wake-sources")]
+use crate::task::WakeCounters;
+
+pub use crate::threadpool::{JoinError, JoinHandle, TaskGuard};
+pub use crate::worker_local;
+
+// how many times an idle worker looks for a task before parking
+const MAX_STEAL_ATTEMPTS: u32 = 16;
+
+// upper bound of the random spins added to each backoff step
+const MAX_STEAL_JITTER: usize = 64;
+
+// Task::last_worker of a task that hasn't run yet
+#[cfg(feature = "task-registry")]
+pub(crate) const NO_WORKER: usize = usize::MAX;
+
+// Task::queued_at of a task that isn't waiting in a queue
+const NOT_QUEUED: u64 = u64::MAX;
+
+// how often a drained worker checks its local queue for stragglers
+const DRAIN_RECHECK_INTERVAL: Duration = Duration::from_millis(10);
+const DEFAULT_TIMER_GRANULARITY: Duration = Duration::from_millis(1);
+// how often Handle::spawn_throttled checks whether the queued tasks went
+// back under Builder::max_queued_task_bytes
+#[cfg(feature = "memory-accounting")]
+const QUEUE_FULL_RECHECK_INTERVAL: Duration = Duration::from_millis(1);
 
 thread_local! {
-    static HANDLE: RefCell<Option<Handle>> = RefCell::new(None);
+    static HANDLE: RefCell<Option<Handle>> = const { RefCell::new(None) };
+    // whether the thread is one of the workers, see WorkerLocal
+    static ON_WORKER: Cell<bool> = const { Cell::new(false) };
+    // the task being polled on this thread
+    static CURRENT_TASK: RefCell<Option<Arc<Task<'static>>>> = const { RefCell::new(None) };
+    // set while the future of a task turned away in drain mode is dropped,
+    // see rejecting_spawn
+    static REJECTING: Cell<bool> = const { Cell::new(false) };
 }
 
 #[derive(Clone)]
 pub struct Handle {
     task_sender: crossbeam_channel::Sender<Arc<Task<'static>>>,
+    // where woken tasks are re-enqueued, the same channel as task_sender
+    // unless Builder::spawn_wake_ratio split them
+    wake_sender: crossbeam_channel::Sender<Arc<Task<'static>>>,
     thread_pool: Arc<ThreadPool>,
+    // see Handle::blocking_pool
+    blocking_pools: Arc<Mutex<BTreeMap<String, Arc<ThreadPool>>>>,
+    metrics: Arc<Metrics>,
+    num_workers: usize,
+    health_thresholds: HealthThresholds,
+    // see Handle::health
+    queue_depth_history: Arc<QueueDepthHistory>,
+    watchdog: Option<Arc<Watchdog>>,
+    #[cfg(feature = "task-registry")]
+    registry: Arc<Registry>,
+    // indexed by worker, see Handle::drain_worker
+    workers: Arc<[Arc<WorkerControl>]>,
+    timer: Arc<Timer>,
+    spawn_limiter: Option<Arc<TokenBucket>>,
+    worker_group_size: usize,
+    // see Handle::drain_mode
+    draining: Arc<AtomicBool>,
+    #[cfg(feature = "memory-accounting")]
+    max_queued_task_bytes: Option<usize>,
+    // see Handle::on_shutdown_hook
+    shutdown_hooks: Arc<Mutex<Vec<ShutdownHook>>>,
+    // never sent on, disconnected by Handle::shutdown to wake up the workers
+    // and the block_on callers
+    shutdown_sender: Arc<Mutex<Option<crossbeam_channel::Sender<()>>>>,
+    shutdown: crossbeam_channel::Receiver<()>,
 }
 
-impl Handle {
-    fn new(
-        task_sender: crossbeam_channel::Sender<Arc<Task<'static>>>,
-        thread_pool: Arc<ThreadPool>,
-    ) -> Self {
-        Self {
-            task_sender,
-            thread_pool,
-        }
-    }
+type ShutdownHook = (i32, Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>);
+
+/// The knobs of `Handle::spawn_task` that the spawn variants set.
+#[derive(Default)]
+struct SpawnOptions {
+    name: Option<String>,
+    completion_waker: Option<Waker>,
+    // the worker whose local queue the task goes to, if any
+    worker: Option<usize>,
+    // whether the wakes also go to that worker, see Handle::spawn_in_group
+    pinned: bool,
+    extensions: Extensions,
+    // spawned even in drain mode, see Handle::block_on
+    bypass_drain: bool,
+    // polled once on the spawning thread first, see Handle::spawn_eager
+    eager: bool,
+}
+
+/// Why a task couldn't be spawned. The future is dropped.
+#[derive(thiserror::Error, Debug)]
+pub enum SpawnError {
+    #[error("spawn rate limit exceeded")]
+    RateLimited,
+    /// The runtime is in drain mode, see `Handle::drain_mode`.
+    #[error("runtime is draining")]
+    Draining,
+    /// The tasks waiting for their first poll hold more memory than
+    /// `Builder::max_queued_task_bytes` allows.
+    #[cfg(feature = "memory-accounting")]
+    #[error("queued tasks exceed their memory limit")]
+    QueueFull,
+}
 
+impl Handle {
     /// Future is not needed to be Send since we're doing single threaded but
     /// the ArcWake trait requires it for more general use cases.
     pub fn spawn<R>(&self, future: impl Future<Output = R> + Send + 'static) -> JoinHandle<R>
     where
         R: Send + 'static,
     {
-        let future = Box::pin(async {
-            let boxed: Box<TaskResult> = Box::new(future.await);
-            boxed
-        });
+        self.spawn_task(future, SpawnOptions::default())
+    }
+
+    /// Same as `spawn` but polls the future once on the calling thread
+    /// before handing it over to the workers, for latency-critical work
+    /// that's often ready right away, e.g. a request whose data is already
+    /// buffered. If it completes, no worker is involved and the returned
+    /// handle is already resolved. If not, the task runs on the workers
+    /// from then on, queued by its waker as usual.
+    ///
+    /// The poll holds up the caller for as long as it takes, and a panic
+    /// in it unwinds into the caller.
+    pub fn spawn_eager<R>(&self, future: impl Future<Output = R> + Send + 'static) -> JoinHandle<R>
+    where
+        R: Send + 'static,
+    {
+        self.spawn_task(
+            future,
+            SpawnOptions {
+                eager: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as `spawn` but gives the task a name, reported by debugging tools
+    /// such as `Handle::tasks`.
+    pub fn spawn_named<R>(
+        &self,
+        name: impl Into<String>,
+        future: impl Future<Output = R> + Send + 'static,
+    ) -> JoinHandle<R>
+    where
+        R: Send + 'static,
+    {
+        self.spawn_task(
+            future,
+            SpawnOptions {
+                name: Some(name.into()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as `spawn` but `waker` is also woken once the task finishes
+    /// (or is aborted), so a foreign event loop can learn about the
+    /// completion without polling. A panic in the waker is caught and logged
+    /// so it can't take the worker down.
+    pub fn spawn_with_notify<R>(
+        &self,
+        future: impl Future<Output = R> + Send + 'static,
+        waker: Waker,
+    ) -> JoinHandle<R>
+    where
+        R: Send + 'static,
+    {
+        self.spawn_task(
+            future,
+            SpawnOptions {
+                completion_waker: Some(waker),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as `spawn` but the task is aborted if the returned guard is
+    /// dropped before the task finishes, unlike a `JoinHandle` which lets the
+    /// task run detached. Use `TaskGuard::release` to opt out.
+    pub fn spawn_guarded<R>(&self, future: impl Future<Output = R> + Send + 'static) -> TaskGuard<R>
+    where
+        R: Send + 'static,
+    {
+        TaskGuard::new(self.spawn(future))
+    }
+
+    /// Same as `spawn` but fails with `SpawnError::RateLimited` when the rate
+    /// set by `Builder::spawn_rate_limit` is exceeded, with
+    /// `SpawnError::QueueFull` when the memory limit of
+    /// `Builder::max_queued_task_bytes` is, and with `SpawnError::Draining`
+    /// in drain mode.
+    pub fn try_spawn<R>(
+        &self,
+        future: impl Future<Output = R> + Send + 'static,
+    ) -> Result<JoinHandle<R>, SpawnError>
+    where
+        R: Send + 'static,
+    {
+        if self.is_draining() {
+            return Err(SpawnError::Draining);
+        }
+        #[cfg(feature = "memory-accounting")]
+        if self.queue_full() {
+            return Err(SpawnError::QueueFull);
+        }
+        if let Some(limiter) = &self.spawn_limiter {
+            limiter.try_acquire().map_err(|_| SpawnError::RateLimited)?;
+        }
 
-        let (result_send, result_recv) = crossbeam_channel::bounded(1);
+        Ok(self.spawn(future))
+    }
+
+    // whether the queued tasks hold more than Builder::max_queued_task_bytes
+    #[cfg(feature = "memory-accounting")]
+    fn queue_full(&self) -> bool {
+        self.max_queued_task_bytes
+            .is_some_and(|max| self.metrics.queued_task_bytes() > max)
+    }
+
+    /// Same as `try_spawn` but waits for the rate limiter, and for the
+    /// queued tasks to get under their memory limit, to let the task
+    /// through instead of failing.
+    pub async fn spawn_throttled<R>(
+        &self,
+        future: impl Future<Output = R> + Send + 'static,
+    ) -> JoinHandle<R>
+    where
+        R: Send + 'static,
+    {
+        #[cfg(feature = "memory-accounting")]
+        while self.queue_full() {
+            sleep(QUEUE_FULL_RECHECK_INTERVAL).await;
+        }
+        if let Some(limiter) = &self.spawn_limiter {
+            while let Err(wait) = limiter.try_acquire() {
+                sleep(wait).await;
+            }
+        }
+
+        self.spawn(future)
+    }
+
+    /// Same as `spawn` but hints that the task should run on the worker that
+    /// last ran the task `other`, e.g. so that a consumer shares the caches
+    /// of the producer it talks to. This is best-effort: the task falls back
+    /// to the global queue when `other` is gone or its worker is draining,
+    /// and it may be picked up by any worker once woken up.
+    #[cfg(feature = "task-registry")]
+    pub fn spawn_near<R>(
+        &self,
+        other: TaskId,
+        future: impl Future<Output = R> + Send + 'static,
+    ) -> JoinHandle<R>
+    where
+        R: Send + 'static,
+    {
+        let worker = self
+            .registry
+            .last_worker(other)
+            .filter(|&index| !self.workers[index].draining.load(Ordering::Acquire));
+
+        self.spawn_task(
+            future,
+            SpawnOptions {
+                worker,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as `spawn` but runs the task on one of the workers assigned to
+    /// `group`, so that tasks of the same group, e.g. of one shard, keep
+    /// their shared state hot in the caches of the same few cores. The task
+    /// goes back to its worker's local queue every time it's woken up.
+    ///
+    /// Each group maps to `Builder::worker_group_size` consecutive workers,
+    /// picked from a hash of the key that is stable across runs and builds,
+    /// and the task is assigned one of them at random. This trades load
+    /// balancing for locality: grouped tasks queue behind each other on
+    /// their workers even if the other ones are idle. A task whose worker
+    /// is drained falls back to the global queue.
+    pub fn spawn_in_group<R>(
+        &self,
+        group: impl Hash,
+        future: impl Future<Output = R> + Send + 'static,
+    ) -> JoinHandle<R>
+    where
+        R: Send + 'static,
+    {
+        let mut hasher = FnvHasher::default();
+        group.hash(&mut hasher);
+        let first = hasher.finish() as usize % self.num_workers;
+
+        let size = self.worker_group_size.min(self.num_workers);
+        let offset = random(size);
+        let worker = (0..size)
+            .map(|i| (first + (offset + i) % size) % self.num_workers)
+            .find(|&index| !self.workers[index].draining.load(Ordering::Acquire));
+
+        self.spawn_task(
+            future,
+            SpawnOptions {
+                worker,
+                pinned: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as `spawn` but the task starts with `extensions`, see
+    /// `task::extensions`. That's how to pass extensions on to a child
+    /// task, they're not inherited.
+    pub fn spawn_with_extensions<R>(
+        &self,
+        extensions: Extensions,
+        future: impl Future<Output = R> + Send + 'static,
+    ) -> JoinHandle<R>
+    where
+        R: Send + 'static,
+    {
+        self.spawn_task(
+            future,
+            SpawnOptions {
+                extensions,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn spawn_task<R>(
+        &self,
+        future: impl Future<Output = R> + Send + 'static,
+        options: SpawnOptions,
+    ) -> JoinHandle<R>
+    where
+        R: Send + 'static,
+    {
+        let (result_send, result_recv) = result_channel();
+
+        if !options.bypass_drain && self.is_draining() {
+            debug!("runtime draining, rejecting the task");
+            REJECTING.with(|rejecting| rejecting.set(true));
+            drop(future);
+            REJECTING.with(|rejecting| rejecting.set(false));
+            result_send.send(Err(JoinError::Draining));
+            return JoinHandle::new(result_recv, Some(AbortHandle::rejected()));
+        }
+
+        let future = Box::pin(async { ErasedOutput::new(future.await) });
+
+        #[cfg(feature = "memory-accounting")]
+        let size = std::mem::size_of_val(&*future);
+        self.metrics.task_spawned(
+            #[cfg(feature = "memory-accounting")]
+            size,
+        );
+
+        let SpawnOptions {
+            name,
+            completion_waker,
+            worker,
+            pinned,
+            extensions,
+            bypass_drain: _,
+            eager,
+        } = options;
 
         let task = Arc::new(Task {
-            future: Mutex::new(future),
-            task_sender: self.task_sender.clone(),
+            id: TaskId::next(),
+            name,
+            future: Mutex::new(Some(future)),
+            task_sender: self.wake_sender.clone(),
+            home: worker
+                .filter(|_| pinned)
+                .map(|index| self.workers[index].clone()),
             result_sender: Some(result_send),
+            aborted: AtomicBool::new(false),
+            suspended: AtomicBool::new(false),
+            wake_deferred: AtomicBool::new(false),
+            completion_waker,
+            extensions: Mutex::new(extensions),
+            spawned_at: Instant::now(),
+            // spawning queues the task right away, unless it's polled on
+            // the spawning thread first
+            queued_at: AtomicU64::new(if eager { NOT_QUEUED } else { 0 }),
+            watch: (self.watchdog.is_some() || cfg!(feature = "task-registry"))
+                .then(TaskWatch::new),
+            #[cfg(feature = "memory-accounting")]
+            size,
+            #[cfg(feature = "memory-accounting")]
+            started: AtomicBool::new(false),
+            #[cfg(feature = "task-registry")]
+            last_worker: std::sync::atomic::AtomicUsize::new(NO_WORKER),
+            #[cfg(feature = "wake-sources")]
+            wakes: WakeCounters::default(),
+            #[cfg(feature = "await-locations")]
+            last_await: Mutex::new(None),
         });
 
-        self.task_sender.send(task).unwrap();
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.register(&task);
+        }
+        #[cfg(feature = "task-registry")]
+        self.registry.insert(&task);
+
+        if eager {
+            self.poll_eagerly(&task);
+            return JoinHandle::new(result_recv, Some(AbortHandle::new(task)));
+        }
+
+        let queue = match worker {
+            Some(index) => &self.workers[index].local_sender,
+            None => &self.task_sender,
+        };
+        // if the worker died, anyone else will do
+        if let Err(crossbeam_channel::SendError(task)) = queue.send(task.clone()) {
+            self.task_sender.send(task).unwrap();
+        }
+
+        JoinHandle::new(result_recv, Some(AbortHandle::new(task)))
+    }
+
+    /// Polls a task that was just spawned on the calling thread, see
+    /// `spawn_eager`. The task isn't queued: if it's pending, it's up to
+    /// its waker, the same one the workers poll it with, to queue it.
+    fn poll_eagerly(&self, task: &Arc<Task<'static>>) {
+        let mut slot = task.future.lock().unwrap();
+        let future = slot
+            .as_mut()
+            .expect("a task that was just spawned has its future");
+
+        #[cfg(feature = "memory-accounting")]
+        if !task.started.swap(true, Ordering::Relaxed) {
+            self.metrics.task_started(task.size);
+        }
+        if let Some(watch) = &task.watch {
+            watch.running();
+        }
+
+        // a wake during the poll queues the task already, the worker that
+        // picks it up waits for the poll to return on the future's lock
+        let waker = waker_ref(task);
+        let context = &mut std::task::Context::from_waker(&waker);
+        // the task being polled on this thread, if any, is the spawner,
+        // it's put back once the poll returns
+        let spawner = CURRENT_TASK.with(|current| current.replace(Some(task.clone())));
+        let eager_guard = EagerPollGuard {
+            handle: self,
+            task,
+            spawner,
+        };
+        let poll = future.as_mut().poll(context);
+        drop(eager_guard);
 
-        JoinHandle::new(result_recv)
+        match poll {
+            std::task::Poll::Pending => {
+                debug!("eagerly polled task not ready, leaving it to its waker");
+                if let Some(watch) = &task.watch {
+                    watch.idle();
+                }
+            }
+            std::task::Poll::Ready(result) => {
+                debug!("eagerly polled task finished");
+                *slot = None;
+                task.finish(
+                    &self.metrics,
+                    #[cfg(feature = "task-registry")]
+                    &self.registry,
+                    Ok(result),
+                );
+            }
+        }
     }
 
+    // TODO spawn_io_affine(future): a soft hint to run I/O-bound tasks on the
+    // reactor thread so that waking them from a reactor event doesn't need a
+    // cross-thread hop. There's no reactor yet, I/O readiness is emulated
+    // by blocking in spawn_blocking and waking from that thread (see
+    // StdinListener in dist-sys), so there's no thread to pin the task to.
+    // Revisit once an I/O driver exists.
+
+    // TODO priority inheritance for an async Mutex: when a high priority task
+    // waits on a lock held by a low priority one, boost the holder to the
+    // highest waiter priority until it releases the lock. Neither piece
+    // exists yet: tasks have no priority, every queue is FIFO, and there's
+    // no async Mutex in the crate. Needs a priority aware scheduler first,
+    // then the lock can track its waiters' priorities and revert the boost
+    // on unlock.
+
+    /// Runs `task` on the blocking thread pool. With the `tracing` feature,
+    /// it inherits the trace id of the calling task, see `task::trace_id`.
     pub fn spawn_blocking<F, R>(&self, task: F) -> JoinHandle<R>
     where
         F: FnOnce() -> R + Send + 'static,
         R: std::any::Any + Send + 'static,
     {
-        self.thread_pool.spawn_blocking(task)
+        self.thread_pool.spawn_blocking(inherit_trace_id(task))
+    }
+
+    /// Registers a blocking pool of up to `size` threads under `name`, to
+    /// run one kind of blocking work, e.g. disk I/O or compression, apart
+    /// from the rest with `spawn_blocking_in`, so that a burst of one kind
+    /// can't starve the others. Returns false, leaving the existing pool as
+    /// it is, if there's already a pool named `name`.
+    pub fn blocking_pool(&self, name: impl Into<String>, size: usize) -> bool {
+        let mut pools = self.blocking_pools.lock().unwrap();
+        match pools.entry(name.into()) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(Arc::new(ThreadPool::new(size, 0)));
+                true
+            }
+        }
+    }
+
+    /// Same as `spawn_blocking` but runs `task` on the pool registered as
+    /// `name` with `blocking_pool`.
+    ///
+    /// # Panics
+    ///
+    /// If there's no pool named `name`.
+    pub fn spawn_blocking_in<F, R>(&self, name: &str, task: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: std::any::Any + Send + 'static,
+    {
+        let pool = self
+            .blocking_pools
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| panic!("no blocking pool named {name:?}"));
+        pool.spawn_blocking(inherit_trace_id(task))
+    }
+
+    /// Same as `spawn_blocking` but waits for a permit of `limiter` first,
+    /// held until the task returns. Sharing a limiter caps how many tasks of
+    /// one kind run at once, e.g. disk reads, however big the pool is.
+    pub async fn spawn_blocking_limited<F, R>(
+        &self,
+        limiter: &Arc<Semaphore>,
+        task: F,
+    ) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: std::any::Any + Send + 'static,
+    {
+        let permit = limiter.clone().acquire_owned().await;
+        self.spawn_blocking(move || {
+            let _permit = permit;
+            task()
+        })
+    }
+
+    /// Abandons the blocking tasks that haven't started yet, e.g. for a fast
+    /// shutdown. See `ThreadPool::abort_pending`.
+    pub fn abort_pending_blocking(&self) -> usize {
+        self.thread_pool.abort_pending()
     }
 
+    /// Runs `future` to completion, blocking the calling thread. Works in
+    /// drain mode too since the caller waits for it anyway.
+    ///
+    /// # Panics
+    ///
+    /// If the future didn't complete, see `try_block_on`.
     pub fn block_on<R>(&self, future: impl Future<Output = R> + Send + 'static) -> R
     where
         R: Send + 'static,
     {
-        self.spawn(future).join()
+        self.try_block_on(future)
+            .unwrap_or_else(|error| panic!("block_on future didn't complete: {error}"))
+    }
+
+    /// Like `block_on` but returns an error instead of panicking when the
+    /// future doesn't complete. It can't be aborted since its handle is
+    /// never exposed, so that happens only when it panicked and took its
+    /// worker down, `JoinError::Panic`, or when the runtime is shut down
+    /// first, `JoinError::Shutdown`, see `Handle::shutdown`.
+    pub fn try_block_on<R>(
+        &self,
+        future: impl Future<Output = R> + Send + 'static,
+    ) -> Result<R, JoinError>
+    where
+        R: Send + 'static,
+    {
+        self.spawn_task(
+            future,
+            SpawnOptions {
+                bypass_drain: true,
+                ..Default::default()
+            },
+        )
+        .join_or_shutdown(&self.shutdown)
+    }
+
+    /// Drives `future` on the calling thread for up to `budget`, returning
+    /// `Poll::Pending` if it isn't done by then. The future keeps its state
+    /// so the caller can do something else, e.g. render a frame of a GUI,
+    /// and call again to pick up where it left off. The future is polled at
+    /// least once even with a zero budget.
+    ///
+    /// Tasks spawned by the future still run on the workers.
+    pub fn block_on_for<F: Future>(
+        &self,
+        mut future: Pin<&mut F>,
+        budget: Duration,
+    ) -> std::task::Poll<F::Output> {
+        let deadline = Instant::now() + budget;
+        let signal = Arc::new(Signal::default());
+        let waker = waker_ref(&signal);
+        let context = &mut std::task::Context::from_waker(&waker);
+
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(context) {
+                return std::task::Poll::Ready(output);
+            }
+
+            if !signal.wait_until(deadline) {
+                return std::task::Poll::Pending;
+            }
+        }
+    }
+
+    /// How many workers are running or looking for tasks right now, as
+    /// opposed to parked or drained. Compare with the configured number of
+    /// workers to see how much parallelism is actually achieved. Only two
+    /// atomic loads, cheap enough to sample often.
+    pub fn effective_parallelism(&self) -> usize {
+        self.metrics.active_workers()
+    }
+
+    pub fn metrics(&self) -> RuntimeMetrics {
+        let global_queue_depth = if self.task_sender.same_channel(&self.wake_sender) {
+            self.task_sender.len()
+        } else {
+            self.task_sender.len() + self.wake_sender.len()
+        };
+
+        let blocking_pools = self
+            .blocking_pools
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, pool)| BlockingPoolMetrics {
+                name: name.clone(),
+                threads: pool.num_threads(),
+                busy_threads: pool.busy_threads(),
+                queue_depth: pool.queue_depth(),
+            })
+            .collect();
+
+        self.metrics.snapshot(
+            global_queue_depth,
+            self.thread_pool.num_threads(),
+            self.thread_pool.overflow_threads(),
+            blocking_pools,
+        )
+    }
+
+    /// Lists the tasks that are currently alive.
+    #[cfg(feature = "task-registry")]
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        self.registry.tasks()
+    }
+
+    /// Finds a live task by id, `None` once it's done.
+    #[cfg(feature = "task-registry")]
+    pub fn task(&self, id: TaskId) -> Option<AbortHandle> {
+        self.registry.get(id).map(AbortHandle::new)
+    }
+
+    /// Debugging figures of a live task, `None` once it's done:
+    ///
+    /// - with `wake-sources`, what has been waking it so far. Only the wakes
+    ///   labeled with `task::with_wake_source` are attributed, the rest
+    ///   count as `WakeSource::Manual`.
+    /// - with `await-locations`, the sleep, timeout, interval, semaphore
+    ///   acquire or I/O future of the crate it's suspended at, and where
+    ///   that future was created. The first thing to look at for a task
+    ///   that hangs.
+    #[cfg(any(feature = "wake-sources", feature = "await-locations"))]
+    pub fn task_stats(&self, id: TaskId) -> Option<TaskStats> {
+        self.registry.stats(id)
+    }
+
+    /// Turns drain mode on or off. While it's on, new tasks are turned
+    /// away: `try_spawn` fails with `SpawnError::Draining` and the handles
+    /// returned by `spawn` and its variants resolve to
+    /// `JoinError::Draining` right away, without running the future. Tasks
+    /// that are already spawned keep running to completion, e.g. to quiesce
+    /// the runtime before a config change without shutting it down.
+    /// `block_on` and blocking tasks are still let through.
+    pub fn drain_mode(&self, enabled: bool) {
+        self.draining.store(enabled, Ordering::Release);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+
+    /// Takes worker `index` out of rotation, e.g. for maintenance. The worker
+    /// finishes the task it's running, if any, hands the tasks in its local
+    /// queue over to the global queue so other workers pick them up, then
+    /// stays parked until `resume_worker(index)`.
+    ///
+    /// Returns without waiting for the worker, see `is_worker_drained`.
+    /// Panics if `index` is not below the number of workers.
+    pub fn drain_worker(&self, index: usize) {
+        self.workers[index].drain();
+    }
+
+    /// Puts a worker taken out by `drain_worker` back into rotation.
+    pub fn resume_worker(&self, index: usize) {
+        self.workers[index].resume();
+    }
+
+    /// Whether worker `index` has finished draining and is now parked.
+    pub fn is_worker_drained(&self, index: usize) -> bool {
+        *self.workers[index].drained.lock().unwrap()
+    }
+
+    /// Registers `hook` to run on `shutdown_gracefully`, e.g. to flush
+    /// buffers or deregister from service discovery. Hooks with a higher
+    /// `priority` run first, those with the same priority in the order they
+    /// were registered.
+    pub fn on_shutdown_hook<F, Fut>(&self, priority: i32, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown_hooks
+            .lock()
+            .unwrap()
+            .push((priority, Box::new(move || hook().boxed())));
+    }
+
+    /// Runs the shutdown hooks, then turns drain mode on. Each hook runs on
+    /// the workers and must complete before the next one starts, so a hook
+    /// can still spawn tasks and wait for them. A hook that panics is
+    /// logged and skipped, the ones after it still run. Hooks run only once,
+    /// calling this again only runs those registered in the meantime.
+    ///
+    /// The workers are left running, `shutdown` stops them.
+    ///
+    /// Returns how many hooks panicked.
+    pub fn shutdown_gracefully(&self) -> usize {
+        let mut hooks = std::mem::take(&mut *self.shutdown_hooks.lock().unwrap());
+        hooks.sort_by_key(|(priority, _)| std::cmp::Reverse(*priority));
+
+        let mut panicked = 0;
+        for (priority, hook) in hooks {
+            // the hook itself may panic before it returns its future
+            let completed =
+                self.block_on(AssertUnwindSafe(async move { hook().await }).catch_unwind());
+            if completed.is_err() {
+                error!("shutdown hook with priority {priority} panicked");
+                panicked += 1;
+            }
+        }
+
+        self.drain_mode(true);
+        panicked
+    }
+
+    /// Stops the workers and turns drain mode on. Each worker stops once
+    /// it's done with the poll it's in, if any. The tasks that haven't
+    /// completed by then never will: a `block_on` in flight, or called
+    /// afterwards, returns `Err(JoinError::Shutdown)` unless its future
+    /// completed before the shutdown. A poll still in progress at that
+    /// moment may or may not make it.
+    ///
+    /// Returns without waiting for the workers. Run `shutdown_gracefully`
+    /// first for the hooks, they need the workers.
+    pub fn shutdown(&self) {
+        self.drain_mode(true);
+        // dropping the only sender wakes up everything waiting on the
+        // receivers
+        self.shutdown_sender.lock().unwrap().take();
+    }
+
+    /// Captures the state of the scheduler, to assert on in tests: which
+    /// tasks are queued or idle, what each worker runs, and the deadlines
+    /// of the timer. Unlike the metrics, which count events over time, it
+    /// says where every task is at one point.
+    ///
+    /// Every part is read separately while the runtime keeps running, so
+    /// the snapshot is only consistent if taken when the runtime is
+    /// quiescent: every task idle or blocked on something the test controls,
+    /// or the workers drained.
+    #[cfg(feature = "task-registry")]
+    pub fn snapshot(&self) -> RuntimeSnapshot {
+        let workers = self
+            .workers
+            .iter()
+            .map(|control| WorkerSnapshot {
+                running: None,
+                local_queue_depth: control.local_sender.len(),
+                drained: *control.drained.lock().unwrap(),
+            })
+            .collect();
+
+        self.registry.snapshot(workers, self.timer.deadlines())
+    }
+
+    pub(crate) fn shared_metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    pub(crate) fn timer(&self) -> Arc<Timer> {
+        self.timer.clone()
+    }
+
+    /// A ready-made signal for load balancer health checks, see
+    /// `HealthStatus::assess` for how the status is derived. Each call
+    /// samples the global queue depth, so call it periodically for the
+    /// queue depth trend to be followed.
+    pub fn health(&self) -> HealthStatus {
+        let metrics = self.metrics();
+        let queue_depths = self.queue_depth_history.sample(
+            metrics.global_queue_depth,
+            self.health_thresholds.trend_samples,
+        );
+        HealthStatus::assess(
+            &metrics,
+            &queue_depths,
+            self.num_workers,
+            &self.health_thresholds,
+        )
     }
 }
 
@@ -90,102 +891,930 @@ pub fn set_current(handle: Handle) {
     });
 }
 
+/// A value with one instance per worker thread, declared with
+/// `worker_local!`. Meant for things like per-worker connection pools or
+/// scratch buffers that tasks can borrow without synchronization.
+///
+/// Unlike a task-local value, it doesn't follow the task around: a task that
+/// is woken up on another worker after an `await` sees that worker's
+/// instance, so don't hold on to anything across awaits.
+pub struct WorkerLocal<T: 'static> {
+    key: &'static std::thread::LocalKey<T>,
+}
+
+impl<T: 'static> WorkerLocal<T> {
+    #[doc(hidden)]
+    pub const fn new(key: &'static std::thread::LocalKey<T>) -> Self {
+        Self { key }
+    }
+
+    /// Runs `f` with the instance of the current worker. Panics when not
+    /// called from a task running on a worker.
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.try_with(f)
+            .expect("worker-local value accessed outside of a worker thread")
+    }
+
+    /// Same as `with` but returns `None` when not on a worker.
+    pub fn try_with<F, R>(&'static self, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        ON_WORKER.with(Cell::get).then(|| self.key.with(f))
+    }
+}
+
+/// Runs `f` with the extensions of the task being polled on this thread, or
+/// returns `None` outside of a task. See `task::extensions`.
+pub(crate) fn with_current_extensions<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut Extensions) -> R,
+{
+    CURRENT_TASK.with(|current| {
+        let current = current.borrow();
+        let task = current.as_ref()?;
+        let mut extensions = task
+            .extensions
+            .try_lock()
+            .expect("task extensions are already borrowed");
+        Some(f(&mut extensions))
+    })
+}
+
+/// Records where the task being polled is suspended, see `AwaitSite`.
+#[cfg(feature = "await-locations")]
+pub(crate) fn set_current_await_point(point: AwaitPoint) {
+    CURRENT_TASK.with(|current| {
+        if let Some(task) = current.borrow().as_ref() {
+            *task.last_await.lock().unwrap() = Some(point);
+        }
+    });
+}
+
+/// Wraps a blocking task so that it inherits the trace id of the calling
+/// task, see `task::trace_id`. Returns it as is without the `tracing`
+/// feature.
+fn inherit_trace_id<F, R>(task: F) -> impl FnOnce() -> R + Send + 'static
+where
+    F: FnOnce() -> R + Send + 'static,
+{
+    #[cfg(feature = "tracing")]
+    {
+        let trace_id = crate::task::trace_id();
+        move || crate::task::with_trace_id(trace_id, task)
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    task
+}
+
 pub fn new_runtime(num_worker: usize, max_blocking_threads: usize) -> Handle {
-    let thread_pool = Arc::new(ThreadPool::new(max_blocking_threads + num_worker));
+    Builder::new()
+        .worker_threads(num_worker)
+        .max_blocking_threads(max_blocking_threads)
+        .build()
+}
+
+pub struct Builder {
+    worker_threads: usize,
+    max_blocking_threads: usize,
+    blocking_overflow: usize,
+    health_thresholds: HealthThresholds,
+    spawn_wake_ratio: Option<ServiceRatio>,
+    heartbeat: Option<(Duration, Arc<HeartbeatCallback>)>,
+    timer_granularity: Duration,
+    spawn_rate_limit: Option<u32>,
+    worker_group_size: usize,
+    auto_scale: Option<AutoScaleConfig>,
+    #[cfg(feature = "memory-accounting")]
+    max_queued_task_bytes: Option<usize>,
+}
+
+/// How many tasks a worker takes from the spawn queue and from the wake
+/// queue in turn, see `Builder::spawn_wake_ratio`.
+#[derive(Debug, Clone, Copy)]
+struct ServiceRatio {
+    spawns: u32,
+    wakes: u32,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            worker_threads: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            max_blocking_threads: 32,
+            blocking_overflow: 0,
+            health_thresholds: HealthThresholds::default(),
+            spawn_wake_ratio: None,
+            heartbeat: None,
+            timer_granularity: DEFAULT_TIMER_GRANULARITY,
+            spawn_rate_limit: None,
+            worker_group_size: 1,
+            auto_scale: None,
+            #[cfg(feature = "memory-accounting")]
+            max_queued_task_bytes: None,
+        }
+    }
 
-    let (global_send, global_recv) = crossbeam_channel::unbounded::<Arc<Task>>();
+    /// Panics if `worker_threads` is zero, there would be nothing to run the
+    /// tasks on.
+    pub fn worker_threads(mut self, worker_threads: usize) -> Self {
+        assert!(
+            worker_threads > 0,
+            "worker threads must be greater than zero"
+        );
+        self.worker_threads = worker_threads;
+        self
+    }
+
+    pub fn max_blocking_threads(mut self, max_blocking_threads: usize) -> Self {
+        self.max_blocking_threads = max_blocking_threads;
+        self
+    }
+
+    /// Lets the blocking pool start up to `max` threads beyond
+    /// `max_blocking_threads` while tasks are queued with no idle thread to
+    /// take them, to absorb a spike without keeping an oversized pool
+    /// around. The extra threads exit once they have been idle for a
+    /// little while, like the regular ones. Off by default.
+    pub fn blocking_overflow(mut self, max: usize) -> Self {
+        self.blocking_overflow = max;
+        self
+    }
+
+    pub fn health_thresholds(mut self, health_thresholds: HealthThresholds) -> Self {
+        self.health_thresholds = health_thresholds;
+        self
+    }
+
+    /// Queues newly spawned tasks and woken tasks separately. Workers then
+    /// take up to `spawns` tasks from the spawn queue followed by up to
+    /// `wakes` tasks from the wake queue, falling back to the other queue
+    /// when the preferred one is empty. This keeps a flood of wakes from a
+    /// few chatty tasks from delaying fresh spawns, and the other way around.
+    ///
+    /// By default both go through a single queue in arrival order.
+    pub fn spawn_wake_ratio(mut self, spawns: u32, wakes: u32) -> Self {
+        assert!(
+            spawns > 0 && wakes > 0,
+            "both sides of the ratio must be greater than zero"
+        );
+        self.spawn_wake_ratio = Some(ServiceRatio { spawns, wakes });
+        self
+    }
+
+    /// Reports every live task to `callback` each `interval`, so a watchdog
+    /// can spot the stuck ones, see `TaskHeartbeat::suspicious`. Off by
+    /// default since it tracks the state of each task on every poll and wake.
+    ///
+    /// The callback runs on a dedicated thread and should return quickly.
+    pub fn heartbeat(
+        mut self,
+        interval: Duration,
+        callback: impl Fn(&[TaskHeartbeat]) + Send + Sync + 'static,
+    ) -> Self {
+        self.heartbeat = Some((interval, Arc::new(callback)));
+        self
+    }
 
-    let handle = Handle::new(global_send.clone(), thread_pool.clone());
+    /// Deadlines of `time::sleep` and friends are rounded up to a multiple
+    /// of `granularity`, so that the timer thread wakes up once to fire all
+    /// the deadlines falling within the same window. A coarser granularity
+    /// means fewer wakeups at the cost of precision. Defaults to 1ms.
+    pub fn timer_granularity(mut self, granularity: Duration) -> Self {
+        self.timer_granularity = granularity;
+        self
+    }
+
+    /// Limits `Handle::try_spawn` and `Handle::spawn_throttled` to
+    /// `per_second` spawns a second, with bursts of up to as many. Protects
+    /// the scheduler from runaway spawn loops. Plain `Handle::spawn` is not
+    /// limited. Off by default.
+    pub fn spawn_rate_limit(mut self, per_second: u32) -> Self {
+        assert!(per_second > 0, "spawn rate limit must be greater than zero");
+        self.spawn_rate_limit = Some(per_second);
+        self
+    }
 
-    set_current(handle.clone());
+    /// Caps the total size of the futures of the tasks that no worker has
+    /// picked up yet, see `RuntimeMetrics::queued_task_bytes`: past `bytes`,
+    /// `Handle::try_spawn` fails and `Handle::spawn_throttled` waits until
+    /// the workers catch up. Protects against a flood of large futures
+    /// running out of memory before any of them gets to run. Plain
+    /// `Handle::spawn` is not limited. Off by default.
+    #[cfg(feature = "memory-accounting")]
+    pub fn max_queued_task_bytes(mut self, bytes: usize) -> Self {
+        self.max_queued_task_bytes = Some(bytes);
+        self
+    }
+
+    /// How many workers the tasks of each group are spread over, see
+    /// `Handle::spawn_in_group`. Defaults to 1, capped at the number of
+    /// workers.
+    pub fn worker_group_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "worker group size must be greater than zero");
+        self.worker_group_size = size;
+        self
+    }
 
-    for _ in 0..num_worker {
-        let executor = Worker::new(global_recv.clone());
-        thread_pool.spawn_blocking(move || executor.run());
+    /// Grows and shrinks the number of workers in rotation with the global
+    /// queue depth, between `config.min_workers` and `worker_threads`, see
+    /// `AutoScaleConfig`. Every scaling is logged and counted in the
+    /// metrics. Off by default, all the workers are then always in
+    /// rotation.
+    pub fn auto_scale(mut self, config: AutoScaleConfig) -> Self {
+        assert!(
+            config.min_workers > 0,
+            "auto-scaling needs at least one worker"
+        );
+        self.auto_scale = Some(config);
+        self
     }
 
-    handle
+    /// Starts the workers and sets the runtime as the current one for the
+    /// calling thread.
+    pub fn build(self) -> Handle {
+        let thread_pool = Arc::new(ThreadPool::new(
+            self.max_blocking_threads + self.worker_threads,
+            self.blocking_overflow,
+        ));
+
+        let (global_send, global_recv) = crossbeam_channel::unbounded::<Arc<Task>>();
+
+        let (wake_send, wake_recv) = match self.spawn_wake_ratio {
+            Some(_) => crossbeam_channel::unbounded::<Arc<Task>>(),
+            None => (global_send.clone(), crossbeam_channel::never()),
+        };
+
+        let (shutdown_send, shutdown_recv) = crossbeam_channel::bounded(0);
+
+        let metrics = Arc::new(Metrics::new(self.worker_threads));
+        #[cfg(feature = "task-registry")]
+        let registry = Arc::new(Registry::default());
+
+        let workers: Vec<_> = (0..self.worker_threads)
+            .map(|index| {
+                let (local_send, local_recv) = crossbeam_channel::unbounded();
+                let (wakeup_send, wakeup_recv) = crossbeam_channel::bounded(1);
+                let control = Arc::new(WorkerControl {
+                    local_sender: local_send,
+                    draining: AtomicBool::new(false),
+                    drained: Mutex::new(false),
+                    resumed: Condvar::new(),
+                    wakeup: wakeup_send,
+                });
+                let worker = Worker {
+                    index,
+                    local_queue: local_recv,
+                    global_queue: global_recv.clone(),
+                    wake_queue: wake_recv.clone(),
+                    spawn_wake_ratio: self.spawn_wake_ratio,
+                    turn: Cell::new(0),
+                    #[cfg(feature = "task-registry")]
+                    registry: registry.clone(),
+                    global_sender: global_send.clone(),
+                    metrics: metrics.clone(),
+                    control: control.clone(),
+                    wakeup: wakeup_recv,
+                    shutdown: shutdown_recv.clone(),
+                };
+                (worker, control)
+            })
+            .collect();
+
+        let workers_control: Arc<[_]> =
+            workers.iter().map(|(_, control)| control.clone()).collect();
+        let timer = Timer::start(self.timer_granularity);
+        if let Some(config) = self.auto_scale {
+            AutoScaler::start(
+                config,
+                &timer,
+                workers_control.clone(),
+                metrics.clone(),
+                global_recv.clone(),
+                wake_recv.clone(),
+            );
+        }
+
+        let handle = Handle {
+            task_sender: global_send,
+            wake_sender: wake_send,
+            thread_pool: thread_pool.clone(),
+            blocking_pools: Default::default(),
+            metrics: metrics.clone(),
+            num_workers: self.worker_threads,
+            health_thresholds: self.health_thresholds,
+            queue_depth_history: Default::default(),
+            watchdog: self
+                .heartbeat
+                .map(|(interval, callback)| Watchdog::start(interval, callback)),
+            #[cfg(feature = "task-registry")]
+            registry,
+            workers: workers_control,
+            timer,
+            spawn_limiter: self
+                .spawn_rate_limit
+                .map(|per_second| Arc::new(TokenBucket::new(per_second))),
+            worker_group_size: self.worker_group_size,
+            draining: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "memory-accounting")]
+            max_queued_task_bytes: self.max_queued_task_bytes,
+            shutdown_hooks: Default::default(),
+            shutdown_sender: Arc::new(Mutex::new(Some(shutdown_send))),
+            shutdown: shutdown_recv,
+        };
+
+        set_current(handle.clone());
+
+        for (executor, _) in workers {
+            thread_pool.spawn_unabortable(move || executor.run());
+        }
+
+        handle
+    }
 }
 
 struct Worker<'a> {
+    index: usize,
     local_queue: crossbeam_channel::Receiver<Arc<Task<'a>>>,
     global_queue: crossbeam_channel::Receiver<Arc<Task<'a>>>,
-    // the task sender for this local queue
-    task_sender: crossbeam_channel::Sender<Arc<Task<'a>>>,
+    // never receives anything unless spawns and wakes are queued separately
+    wake_queue: crossbeam_channel::Receiver<Arc<Task<'a>>>,
+    spawn_wake_ratio: Option<ServiceRatio>,
+    // position in the spawn/wake rotation
+    turn: Cell<u32>,
+    #[cfg(feature = "task-registry")]
+    registry: Arc<Registry>,
+    // where the local queue is handed over to when draining
+    global_sender: crossbeam_channel::Sender<Arc<Task<'a>>>,
+    metrics: Arc<Metrics>,
+    control: Arc<WorkerControl>,
+    // pokes the worker out of park when it's asked to drain
+    wakeup: crossbeam_channel::Receiver<()>,
+    // disconnected when the runtime shuts down, see Handle::shutdown
+    shutdown: crossbeam_channel::Receiver<()>,
 }
 
-// TODO implement lifetime correctly
-impl Worker<'static> {
-    fn new(global_queue: crossbeam_channel::Receiver<Arc<Task<'static>>>) -> Self {
-        let (sender, queue) = crossbeam_channel::unbounded::<Arc<Task>>();
-        Self {
-            local_queue: queue,
-            global_queue,
-            task_sender: sender,
-        }
+/// The part of a worker that the handle can reach, see
+/// `Handle::drain_worker`.
+pub(crate) struct WorkerControl {
+    // the sending side of the worker's local queue
+    local_sender: crossbeam_channel::Sender<Arc<Task<'static>>>,
+    draining: AtomicBool,
+    // set while the worker is parked after draining
+    drained: Mutex<bool>,
+    resumed: Condvar,
+    wakeup: crossbeam_channel::Sender<()>,
+}
+
+impl WorkerControl {
+    pub(crate) fn drain(&self) {
+        self.draining.store(true, Ordering::Release);
+        // unpark the worker if it's waiting for tasks, a full channel means
+        // it's already been poked
+        let _ = self.wakeup.try_send(());
     }
 
+    pub(crate) fn resume(&self) {
+        let _drained = self.drained.lock().unwrap();
+        self.draining.store(false, Ordering::Release);
+        self.resumed.notify_one();
+    }
+}
+
+// TODO implement lifetime correctly
+impl Worker<'static> {
     fn run(&self) {
-        // TODO since we're not using crossbeam channel's recv(), we don't get
-        // the benefit of yielding the thread when the channel is empty.
-        // Performance opportunities:
-        // - implement or use crossbeam's Backoff to yield the thread or spin
-        //   when the channel is empty
-        // - park the thread and use signal mechanism to wake up the thread when
-        //   there's a new task
+        ON_WORKER.with(|on_worker| on_worker.set(true));
+        self.metrics.worker_started();
+        // decrement the live worker count even if a task panics on us
+        let _guard = WorkerGuard(&self.metrics);
+
+        // When there's nothing to do, back off with some random jitter so
+        // that idle workers don't hammer the queues in lockstep, then park
+        // once the attempts run out.
+        //
+        // There's no stealing between workers yet so the contended "victim"
+        // is the global queue, but the same storm happens there.
+        let backoff = Backoff::new();
+        let mut failed_attempts = 0;
+
         loop {
-            let mut task: Option<Arc<Task<'static>>> = None;
-
-            // TODO currently we're not spawning into the local queue so this
-            // always returns err
-            if let Ok(t) = self.local_queue.try_recv() {
-                task = Some(t);
-            } else if let Ok(t) = self.global_queue.try_recv() {
-                // TODO consider changing the task_sender of the task to local
-                // queue sender, so that any futures that this task spawns
-                // get queued in the local queue.
-                task = Some(t);
+            if self.shut_down() {
+                debug!("worker shutting down");
+                break;
             }
 
-            if let Some(task) = task {
-                debug!("got task from local queue, running it");
-                let mut future = task.future.lock().unwrap();
-                let waker = waker_ref(&task);
-                let context = &mut std::task::Context::from_waker(&waker);
+            if self.control.draining.load(Ordering::Acquire) {
+                self.drain();
+                backoff.reset();
+                failed_attempts = 0;
+            }
 
-                match future.as_mut().poll(context) {
-                    std::task::Poll::Pending => {
-                        debug!("task not ready");
-                    }
-                    std::task::Poll::Ready(result) => {
-                        debug!("task finished");
-                        if let Some(result_sender) = &task.result_sender {
-                            // ignore the error because there are cases
-                            // where the caller doesn't need the JoinHandle
-                            // thus it's dropped and the result channel is
-                            // closed
-                            let _ = result_sender.send(result);
+            let task = match self.next_task() {
+                Some(task) => task,
+                None => {
+                    self.metrics.failed_steal();
+                    failed_attempts += 1;
+
+                    if failed_attempts < MAX_STEAL_ATTEMPTS {
+                        for _ in 0..random(MAX_STEAL_JITTER) {
+                            std::hint::spin_loop();
                         }
+                        backoff.snooze();
+                        continue;
+                    }
+
+                    match self.park() {
+                        Ok(Some(task)) => task,
+                        // poked by the handle or shut down, go check why
+                        Ok(None) => continue,
+                        // every sender is gone so nothing can be spawned
+                        // anymore
+                        Err(_) => break,
                     }
                 }
+            };
+
+            backoff.reset();
+            failed_attempts = 0;
+
+            self.run_task(task);
+        }
+    }
+
+    fn next_task(&self) -> Option<Arc<Task<'static>>> {
+        // only tasks spawned with a locality hint or in a group land here
+        if let Ok(t) = self.local_queue.try_recv() {
+            return Some(t);
+        }
+
+        // TODO consider changing the task_sender of the task to local
+        // queue sender, so that any futures that this task spawns
+        // get queued in the local queue.
+        let Some(ratio) = self.spawn_wake_ratio else {
+            return self.global_queue.try_recv().ok();
+        };
+
+        let turn = self.turn.get();
+        self.turn.set((turn + 1) % (ratio.spawns + ratio.wakes));
+
+        let (preferred, other) = if turn < ratio.spawns {
+            (&self.global_queue, &self.wake_queue)
+        } else {
+            (&self.wake_queue, &self.global_queue)
+        };
+
+        preferred.try_recv().or_else(|_| other.try_recv()).ok()
+    }
+
+    /// Blocks the thread until a task shows up in one of the queues, or
+    /// until the handle pokes the worker in which case there's no task.
+    fn park(&self) -> Result<Option<Arc<Task<'static>>>, crossbeam_channel::RecvError> {
+        debug!("worker parking");
+        self.metrics.worker_parked();
+
+        let task = crossbeam_channel::select! {
+            recv(self.local_queue) -> task => task.map(Some),
+            recv(self.global_queue) -> task => task.map(Some),
+            recv(self.wake_queue) -> task => task.map(Some),
+            recv(self.wakeup) -> _ => Ok(None),
+            recv(self.shutdown) -> _ => Ok(None),
+        };
+
+        self.metrics.worker_unparked();
+        debug!("worker unparked");
+
+        task
+    }
+
+    /// Hands the local queue over to the other workers and parks until the
+    /// handle resumes this worker.
+    fn drain(&self) {
+        debug!("draining worker");
+        let migrated = self.migrate_local_queue();
+        debug!("migrated {migrated} tasks off the drained worker");
+
+        self.metrics.worker_parked();
+        let mut drained = self.control.drained.lock().unwrap();
+        *drained = true;
+        while self.control.draining.load(Ordering::Acquire) && !self.shut_down() {
+            (drained, _) = self
+                .control
+                .resumed
+                .wait_timeout(drained, DRAIN_RECHECK_INTERVAL)
+                .unwrap();
+            // grouped tasks woken up right as the drain started may still
+            // have been queued here
+            self.migrate_local_queue();
+        }
+        *drained = false;
+        drop(drained);
+        self.metrics.worker_unparked();
+        debug!("worker resumed");
+    }
+
+    fn shut_down(&self) -> bool {
+        matches!(
+            self.shutdown.try_recv(),
+            Err(crossbeam_channel::TryRecvError::Disconnected)
+        )
+    }
+
+    fn migrate_local_queue(&self) -> usize {
+        let mut migrated = 0;
+        for task in self.local_queue.try_iter() {
+            // we hold a receiver of the global queue ourselves so this
+            // can't fail
+            self.global_sender
+                .send(task)
+                .expect("global queue is never disconnected while a worker runs");
+            migrated += 1;
+        }
+        migrated
+    }
+
+    fn run_task(&self, task: Arc<Task<'static>>) {
+        debug!("got task from the queue, running it");
+        let mut slot = match task.future.lock() {
+            Ok(slot) => slot,
+            // the task panicked on another worker, which already reported
+            // it lost, this is a stale wake up
+            Err(_) => return,
+        };
+
+        // the task has already finished or was aborted, this is a
+        // stale wake up
+        let Some(future) = slot.as_mut() else {
+            return;
+        };
+
+        #[cfg(feature = "memory-accounting")]
+        if !task.started.swap(true, Ordering::Relaxed) {
+            self.metrics.task_started(task.size);
+        }
+
+        if task.aborted.load(Ordering::Acquire) {
+            debug!("task aborted, dropping its future");
+            *slot = None;
+            self.finish(&task, Err(JoinError::Cancelled));
+            return;
+        }
+
+        // woken up before it was suspended, hold on to the wake until resume
+        if task.suspended.load(Ordering::SeqCst) && !task.defer_wake() {
+            debug!("task suspended, skipping it");
+            return;
+        }
+
+        let waker = waker_ref(&task);
+        let context = &mut std::task::Context::from_waker(&waker);
+
+        if let Some(watch) = &task.watch {
+            watch.running();
+        }
+        #[cfg(feature = "task-registry")]
+        task.last_worker.store(self.index, Ordering::Relaxed);
+        // only what this poll ends up pending on counts
+        #[cfg(feature = "await-locations")]
+        task.last_await.lock().unwrap().take();
+
+        let started = Instant::now();
+        // a task woken several times before a worker got to it is queued as
+        // many times, only the first pickup counts
+        let queued_at = task.queued_at.swap(NOT_QUEUED, Ordering::Relaxed);
+        if queued_at != NOT_QUEUED {
+            let queued_for = started.duration_since(task.spawned_at).as_nanos() as u64;
+            self.metrics
+                .task_dequeued(Duration::from_nanos(queued_for.saturating_sub(queued_at)));
+        }
+        let cpu_started = thread_cpu_time();
+        CURRENT_TASK.with(|current| *current.borrow_mut() = Some(task.clone()));
+        let poll = match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(context))) {
+            Ok(poll) => poll,
+            Err(payload) => {
+                // whoever joins the task gets the payload, the worker still
+                // goes down with it
+                error!("task panicked, the worker is going down with it");
+                self.finish(&task, Err(JoinError::Panic(payload)));
+                panic::resume_unwind(Box::new("task panicked"));
+            }
+        };
+        CURRENT_TASK.with(|current| *current.borrow_mut() = None);
+        let cpu = thread_cpu_time()
+            .zip(cpu_started)
+            .map(|(now, started)| now.saturating_sub(started));
+        self.metrics.task_polled(self.index, started.elapsed(), cpu);
+
+        match poll {
+            std::task::Poll::Pending => {
+                debug!("task not ready");
+                if let Some(watch) = &task.watch {
+                    watch.idle();
+                }
+            }
+            std::task::Poll::Ready(result) => {
+                debug!("task finished");
+                *slot = None;
+                self.finish(&task, Ok(result));
+            }
+        }
+    }
+
+    fn finish(&self, task: &Task<'static>, output: TaskOutput) {
+        task.finish(
+            &self.metrics,
+            #[cfg(feature = "task-registry")]
+            &self.registry,
+            output,
+        );
+    }
+}
+
+/// Wakes up a thread blocked in `Handle::block_on_for`.
+#[derive(Default)]
+struct Signal {
+    woken: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Signal {
+    /// Waits to be woken up. Returns false if the deadline passed first.
+    fn wait_until(&self, deadline: Instant) -> bool {
+        let mut woken = self.woken.lock().unwrap();
+        while !*woken {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                return false;
             }
+            woken = self.condvar.wait_timeout(woken, timeout).unwrap().0;
+        }
+
+        *woken = false;
+        true
+    }
+}
+
+impl ArcWake for Signal {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        *arc_self.woken.lock().unwrap() = true;
+        arc_self.condvar.notify_one();
+    }
+}
+
+/// Armed around the inline poll of `Handle::spawn_eager`: puts the
+/// spawner back as the current task, and if the future panics, finishes the
+/// task as `JoinError::Lost` before the panic reaches the caller.
+struct EagerPollGuard<'a> {
+    handle: &'a Handle,
+    task: &'a Task<'static>,
+    spawner: Option<Arc<Task<'static>>>,
+}
+
+impl Drop for EagerPollGuard<'_> {
+    fn drop(&mut self) {
+        CURRENT_TASK.with(|current| *current.borrow_mut() = self.spawner.take());
+        if std::thread::panicking() {
+            error!("task panicked while polled eagerly on the spawning thread");
+            self.task.finish(
+                &self.handle.metrics,
+                #[cfg(feature = "task-registry")]
+                &self.handle.registry,
+                Err(JoinError::Lost),
+            );
         }
     }
 }
 
-type TaskResult = dyn Any + Send + 'static;
+struct WorkerGuard<'a>(&'a Metrics);
+
+impl Drop for WorkerGuard<'_> {
+    fn drop(&mut self) {
+        self.0.worker_stopped();
+    }
+}
+
+type BoxedFuture<'a> = Pin<Box<dyn Future<Output = ErasedOutput> + Send + 'a>>;
+
+/// Unique identifier of a spawned task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Display for TaskId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
-struct Task<'a> {
-    future: Mutex<Pin<Box<dyn Future<Output = Box<TaskResult>> + Send + 'a>>>,
+pub(crate) struct Task<'a> {
+    pub(crate) id: TaskId,
+    pub(crate) name: Option<String>,
+    // None once the future has completed or the task was aborted
+    future: Mutex<Option<BoxedFuture<'a>>>,
     task_sender: crossbeam_channel::Sender<Arc<Task<'a>>>,
-    result_sender: Option<crossbeam_channel::Sender<Box<TaskResult>>>,
+    result_sender: Option<ResultSender>,
+    aborted: AtomicBool,
+    // see AbortHandle::suspend
+    suspended: AtomicBool,
+    // a wake came in while suspended, to be replayed on resume
+    wake_deferred: AtomicBool,
+    // the worker the task is queued on when woken up, see
+    // Handle::spawn_in_group
+    home: Option<Arc<WorkerControl>>,
+    // woken after the result is sent, see Handle::spawn_with_notify
+    completion_waker: Option<Waker>,
+    // see task::extensions
+    extensions: Mutex<Extensions>,
+    spawned_at: Instant,
+    // nanoseconds since spawned_at at which the task was first queued since
+    // it was last picked up, for the scheduling delay metrics
+    queued_at: AtomicU64,
+    // only tracked when the heartbeat or the task registry is enabled
+    pub(crate) watch: Option<TaskWatch>,
+    // size of the boxed future, recorded at spawn for the memory metrics
+    #[cfg(feature = "memory-accounting")]
+    size: usize,
+    // whether a worker picked the task up yet, its size counts as queued
+    // until then
+    #[cfg(feature = "memory-accounting")]
+    started: AtomicBool,
+    // index of the worker that polled the task last, see Handle::spawn_near
+    #[cfg(feature = "task-registry")]
+    pub(crate) last_worker: std::sync::atomic::AtomicUsize,
+    // see Handle::task_stats
+    #[cfg(feature = "wake-sources")]
+    pub(crate) wakes: WakeCounters,
+    // see Handle::task_stats, cleared before each poll
+    #[cfg(feature = "await-locations")]
+    pub(crate) last_await: Mutex<Option<AwaitPoint>>,
+}
+
+impl Task<'static> {
+    /// Hands the output over to the `JoinHandle` once the future completed
+    /// or was dropped.
+    fn finish(
+        &self,
+        metrics: &Metrics,
+        #[cfg(feature = "task-registry")] registry: &Registry,
+        output: TaskOutput,
+    ) {
+        #[cfg(feature = "memory-accounting")]
+        metrics.task_completed(self.size);
+        #[cfg(not(feature = "memory-accounting"))]
+        let _ = metrics;
+        if let Some(watch) = &self.watch {
+            watch.done();
+        }
+        #[cfg(feature = "task-registry")]
+        registry.remove(self.id);
+        if let Some(result_sender) = &self.result_sender {
+            result_sender.send(output);
+        }
+
+        if let Some(waker) = &self.completion_waker {
+            let woken = panic::catch_unwind(AssertUnwindSafe(|| waker.wake_by_ref()));
+            if woken.is_err() {
+                error!("completion waker panicked");
+            }
+        }
+    }
+
+    /// Queues the task to be polled, suspended or not.
+    fn schedule(self: &Arc<Self>) {
+        if let Some(watch) = &self.watch {
+            watch.queued();
+        }
+        // a task woken again while it's still queued keeps its first
+        // enqueue time, see Worker::run_task
+        let _ = self.queued_at.compare_exchange(
+            NOT_QUEUED,
+            self.spawned_at.elapsed().as_nanos() as u64,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+        let cloned = self.to_owned();
+        if let Some(home) = &self.home {
+            if !home.draining.load(Ordering::Acquire) {
+                // if the worker died, anyone else will do
+                let Err(crossbeam_channel::SendError(cloned)) = home.local_sender.send(cloned)
+                else {
+                    return;
+                };
+                self.task_sender.send(cloned).unwrap();
+                return;
+            }
+        }
+        // TODO proper error handling
+        self.task_sender.send(cloned).unwrap();
+    }
+
+    /// Remembers that the suspended task was woken up. Returns whether
+    /// the task got resumed in the meantime, the wake must then go through.
+    fn defer_wake(&self) -> bool {
+        self.wake_deferred.store(true, Ordering::SeqCst);
+        // resume may have missed the flag, whoever clears it first schedules
+        !self.suspended.load(Ordering::SeqCst) && self.wake_deferred.swap(false, Ordering::SeqCst)
+    }
 }
 
 impl ArcWake for Task<'static> {
     fn wake_by_ref(arc_self: &Arc<Self>) {
         debug!("waking task");
-        let cloned = arc_self.to_owned();
-        // TODO proper error handling
-        arc_self.task_sender.send(cloned).unwrap();
+        #[cfg(feature = "wake-sources")]
+        arc_self.wakes.record();
+        if arc_self.suspended.load(Ordering::SeqCst) && !arc_self.defer_wake() {
+            debug!("task suspended, deferring the wake");
+            return;
+        }
+        arc_self.schedule();
+    }
+}
+
+/// Whether the future being dropped on this thread belongs to a task that
+/// was turned away in drain mode, as opposed to one that was aborted, e.g.
+/// to report `JoinError::Draining` from a drop guard inside the future.
+pub(crate) fn rejecting_spawn() -> bool {
+    REJECTING.with(Cell::get)
+}
+
+/// Aborts a spawned task from anywhere, without needing the `JoinHandle`.
+#[derive(Clone)]
+pub struct AbortHandle {
+    id: TaskId,
+    // None for a task turned away in drain mode, which never ran and is
+    // already finished
+    task: Option<Arc<Task<'static>>>,
+}
+
+impl AbortHandle {
+    fn new(task: Arc<Task<'static>>) -> Self {
+        Self {
+            id: task.id,
+            task: Some(task),
+        }
+    }
+
+    fn rejected() -> Self {
+        Self {
+            id: TaskId::next(),
+            task: None,
+        }
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Requests the task to be cancelled. The task's future is dropped the
+    /// next time a worker picks it up, which is right away since aborting
+    /// also wakes the task, and `join` then returns `Err(JoinError::Cancelled)`.
+    /// Aborting a task that already finished does nothing.
+    pub fn abort(&self) {
+        let Some(task) = &self.task else { return };
+        task.aborted.store(true, Ordering::Release);
+        // even a suspended task can be aborted
+        task.schedule();
+    }
+
+    /// Stops the task from being polled until `resume`, e.g. for debugging
+    /// or flow control. A wake that comes in meanwhile is held back and the
+    /// task runs once resumed. A poll that is already under way completes.
+    pub fn suspend(&self) {
+        if let Some(task) = &self.task {
+            task.suspended.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Lets a suspended task run again. Does nothing if it isn't suspended.
+    pub fn resume(&self) {
+        let Some(task) = &self.task else { return };
+        task.suspended.store(false, Ordering::SeqCst);
+        if task.wake_deferred.swap(false, Ordering::SeqCst) {
+            task.schedule();
+        }
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.task
+            .as_ref()
+            .is_some_and(|task| task.suspended.load(Ordering::SeqCst))
     }
 }
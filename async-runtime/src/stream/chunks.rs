@@ -0,0 +1,122 @@
+use std::{
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct Chunks<S: Stream> {
+        #[pin]
+        stream: S,
+        items: Vec<S::Item>,
+        capacity: usize,
+        // the inner stream is not fused, so don't poll it after it ended
+        done: bool,
+    }
+}
+
+impl<S: Stream> Chunks<S> {
+    pub(super) fn new(stream: S, capacity: usize) -> Self {
+        assert!(capacity > 0, "chunk capacity must be greater than zero");
+        Self {
+            stream,
+            items: Vec::with_capacity(capacity),
+            capacity,
+            done: false,
+        }
+    }
+}
+
+impl<S: Stream> Stream for Chunks<S> {
+    type Item = Vec<S::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            match this.stream.as_mut().poll_next(cx) {
+                // the partial batch is kept across pending polls
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(item)) => {
+                    this.items.push(item);
+                    if this.items.len() >= *this.capacity {
+                        let items = Vec::with_capacity(*this.capacity);
+                        return Poll::Ready(Some(mem::replace(this.items, items)));
+                    }
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    if this.items.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(mem::take(this.items)));
+                }
+            }
+        }
+    }
+}
+
+pin_project! {
+    pub struct ReadyChunks<S> {
+        #[pin]
+        stream: S,
+        capacity: usize,
+        done: bool,
+    }
+}
+
+impl<S: Stream> ReadyChunks<S> {
+    pub(super) fn new(stream: S, capacity: usize) -> Self {
+        assert!(capacity > 0, "chunk capacity must be greater than zero");
+        Self {
+            stream,
+            capacity,
+            done: false,
+        }
+    }
+}
+
+impl<S: Stream> Stream for ReadyChunks<S> {
+    type Item = Vec<S::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        let mut items = Vec::new();
+
+        loop {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Pending => {
+                    if items.is_empty() {
+                        return Poll::Pending;
+                    }
+                    return Poll::Ready(Some(items));
+                }
+                Poll::Ready(Some(item)) => {
+                    items.push(item);
+                    if items.len() >= *this.capacity {
+                        return Poll::Ready(Some(items));
+                    }
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    if items.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(items));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque},
+    future::Future,
+    hash::{BuildHasher, Hash},
+    ops::ControlFlow,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+/// A collection that can be built from the items of a stream, see
+/// `StreamExt::collect`. The stream counterpart of `FromIterator`.
+pub trait FromStream<T>: Sized {
+    /// What's built up while the stream is running.
+    type Builder;
+
+    fn builder() -> Self::Builder;
+
+    /// Adds an item. Returning `ControlFlow::Break` stops the stream from
+    /// being polled further, the collection is then finished with what it
+    /// has.
+    fn push(builder: &mut Self::Builder, item: T) -> ControlFlow<()>;
+
+    fn finish(builder: Self::Builder) -> Self;
+}
+
+// the collections that grow with Extend and never stop early
+macro_rules! from_stream_by_extend {
+    ($(impl[$($generics:tt)*] for $collection:ty where [$($bounds:tt)*] from $item:ty;)*) => {
+        $(
+            impl<$($generics)*> FromStream<$item> for $collection
+            where
+                $($bounds)*
+            {
+                type Builder = Self;
+
+                fn builder() -> Self {
+                    Default::default()
+                }
+
+                fn push(builder: &mut Self, item: $item) -> ControlFlow<()> {
+                    builder.extend(Some(item));
+                    ControlFlow::Continue(())
+                }
+
+                fn finish(builder: Self) -> Self {
+                    builder
+                }
+            }
+        )*
+    };
+}
+
+from_stream_by_extend! {
+    impl[T] for Vec<T> where [] from T;
+    impl[T] for VecDeque<T> where [] from T;
+    impl[T] for LinkedList<T> where [] from T;
+    impl[T] for BinaryHeap<T> where [T: Ord] from T;
+    impl[T] for BTreeSet<T> where [T: Ord] from T;
+    impl[T, S] for HashSet<T, S> where [T: Eq + Hash, S: BuildHasher + Default] from T;
+    impl[K, V] for BTreeMap<K, V> where [K: Ord] from (K, V);
+    impl[K, V, S] for HashMap<K, V, S> where [K: Eq + Hash, S: BuildHasher + Default] from (K, V);
+    impl[] for String where [] from char;
+    impl[] for String where [] from String;
+    impl['a] for String where [] from &'a str;
+}
+
+/// Collects the `Ok` values into `C`, stopping at the first `Err` which
+/// becomes the result, like collecting an iterator into a `Result`.
+impl<T, E, C> FromStream<Result<T, E>> for Result<C, E>
+where
+    C: FromStream<T>,
+{
+    type Builder = Result<C::Builder, E>;
+
+    fn builder() -> Self::Builder {
+        Ok(C::builder())
+    }
+
+    fn push(builder: &mut Self::Builder, item: Result<T, E>) -> ControlFlow<()> {
+        match (builder.as_mut(), item) {
+            (Ok(builder), Ok(value)) => C::push(builder, value),
+            (_, Err(error)) => {
+                *builder = Err(error);
+                ControlFlow::Break(())
+            }
+            // an error was pushed already, which stopped the stream
+            (Err(_), Ok(_)) => ControlFlow::Break(()),
+        }
+    }
+
+    fn finish(builder: Self::Builder) -> Self {
+        builder.map(C::finish)
+    }
+}
+
+/// Same as the `Result` one, stopping at the first `None`.
+impl<T, C> FromStream<Option<T>> for Option<C>
+where
+    C: FromStream<T>,
+{
+    type Builder = Option<C::Builder>;
+
+    fn builder() -> Self::Builder {
+        Some(C::builder())
+    }
+
+    fn push(builder: &mut Self::Builder, item: Option<T>) -> ControlFlow<()> {
+        match (builder.as_mut(), item) {
+            (Some(builder), Some(value)) => C::push(builder, value),
+            _ => {
+                *builder = None;
+                ControlFlow::Break(())
+            }
+        }
+    }
+
+    fn finish(builder: Self::Builder) -> Self {
+        builder.map(C::finish)
+    }
+}
+
+pin_project! {
+    pub struct Collect<S: Stream, C: FromStream<S::Item>> {
+        #[pin]
+        stream: S,
+        // taken out once the collection is finished
+        builder: Option<C::Builder>,
+    }
+}
+
+impl<S: Stream, C: FromStream<S::Item>> Collect<S, C> {
+    pub(super) fn new(stream: S) -> Self {
+        Self {
+            stream,
+            builder: Some(C::builder()),
+        }
+    }
+}
+
+impl<S: Stream, C: FromStream<S::Item>> Future for Collect<S, C> {
+    type Output = C;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        let builder = this
+            .builder
+            .as_mut()
+            .expect("Collect polled after completion");
+
+        while let Some(item) = ready!(this.stream.as_mut().poll_next(cx)) {
+            if C::push(builder, item).is_break() {
+                break;
+            }
+        }
+
+        Poll::Ready(C::finish(this.builder.take().unwrap()))
+    }
+}
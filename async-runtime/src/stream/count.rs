@@ -0,0 +1,65 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct Count<S> {
+        #[pin]
+        stream: S,
+        count: usize,
+    }
+}
+
+impl<S> Count<S> {
+    pub(super) fn new(stream: S) -> Self {
+        Self { stream, count: 0 }
+    }
+}
+
+impl<S: Stream> Future for Count<S> {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        while ready!(this.stream.as_mut().poll_next(cx)).is_some() {
+            *this.count += 1;
+        }
+
+        Poll::Ready(*this.count)
+    }
+}
+
+pin_project! {
+    pub struct Last<S: Stream> {
+        #[pin]
+        stream: S,
+        // the latest item so far
+        last: Option<S::Item>,
+    }
+}
+
+impl<S: Stream> Last<S> {
+    pub(super) fn new(stream: S) -> Self {
+        Self { stream, last: None }
+    }
+}
+
+impl<S: Stream> Future for Last<S> {
+    type Output = Option<S::Item>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        while let Some(item) = ready!(this.stream.as_mut().poll_next(cx)) {
+            *this.last = Some(item);
+        }
+
+        Poll::Ready(this.last.take())
+    }
+}
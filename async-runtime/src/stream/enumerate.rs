@@ -0,0 +1,37 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct Enumerate<S> {
+        #[pin]
+        stream: S,
+        // index of the next item
+        count: usize,
+    }
+}
+
+impl<S> Enumerate<S> {
+    pub(super) fn new(stream: S) -> Self {
+        Self { stream, count: 0 }
+    }
+}
+
+impl<S: Stream> Stream for Enumerate<S> {
+    type Item = (usize, S::Item);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let item = ready!(this.stream.poll_next(cx)).map(|item| (*this.count, item));
+        if item.is_some() {
+            *this.count += 1;
+        }
+
+        Poll::Ready(item)
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct FilterMap<S, F> {
+        #[pin]
+        stream: S,
+        f: F,
+    }
+}
+
+impl<S, F> FilterMap<S, F> {
+    pub(super) fn new(stream: S, f: F) -> Self {
+        Self { stream, f }
+    }
+}
+
+impl<S, B, F> Stream for FilterMap<S, F>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> Option<B>,
+{
+    type Item = B;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        while let Some(item) = ready!(this.stream.as_mut().poll_next(cx)) {
+            if let Some(mapped) = (this.f)(item) {
+                return Poll::Ready(Some(mapped));
+            }
+        }
+
+        Poll::Ready(None)
+    }
+}
+
+pin_project! {
+    pub struct MapWhile<S, F> {
+        #[pin]
+        stream: S,
+        f: F,
+        done: bool,
+    }
+}
+
+impl<S, F> MapWhile<S, F> {
+    pub(super) fn new(stream: S, f: F) -> Self {
+        Self {
+            stream,
+            f,
+            done: false,
+        }
+    }
+}
+
+impl<S, B, F> Stream for MapWhile<S, F>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> Option<B>,
+{
+    type Item = B;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        let item = ready!(this.stream.poll_next(cx)).and_then(this.f);
+        if item.is_none() {
+            *this.done = true;
+        }
+
+        Poll::Ready(item)
+    }
+}
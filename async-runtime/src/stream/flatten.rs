@@ -0,0 +1,117 @@
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct Flatten<S: Stream> {
+        #[pin]
+        stream: S,
+        // the inner stream being drained
+        #[pin]
+        current: Option<S::Item>,
+        // the outer stream ended, it's not polled again
+        done: bool,
+    }
+}
+
+impl<S: Stream> Flatten<S> {
+    pub(super) fn new(stream: S) -> Self {
+        Self {
+            stream,
+            current: None,
+            done: false,
+        }
+    }
+}
+
+impl<S> Stream for Flatten<S>
+where
+    S: Stream,
+    S::Item: Stream,
+{
+    type Item = <S::Item as Stream>::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if let Some(current) = this.current.as_mut().as_pin_mut() {
+                match ready!(current.poll_next(cx)) {
+                    Some(item) => return Poll::Ready(Some(item)),
+                    None => this.current.set(None),
+                }
+            }
+
+            if *this.done {
+                return Poll::Ready(None);
+            }
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(next) => this.current.set(Some(next)),
+                None => {
+                    *this.done = true;
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+}
+
+pin_project! {
+    pub struct FlatMap<S, U, F> {
+        #[pin]
+        stream: S,
+        f: F,
+        #[pin]
+        current: Option<U>,
+        // the outer stream ended, it's not polled again
+        done: bool,
+    }
+}
+
+impl<S, U, F> FlatMap<S, U, F> {
+    pub(super) fn new(stream: S, f: F) -> Self {
+        Self {
+            stream,
+            f,
+            current: None,
+            done: false,
+        }
+    }
+}
+
+impl<S, U, F> Stream for FlatMap<S, U, F>
+where
+    S: Stream,
+    U: Stream,
+    F: FnMut(S::Item) -> U,
+{
+    type Item = U::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if let Some(current) = this.current.as_mut().as_pin_mut() {
+                match ready!(current.poll_next(cx)) {
+                    Some(item) => return Poll::Ready(Some(item)),
+                    None => this.current.set(None),
+                }
+            }
+
+            if *this.done {
+                return Poll::Ready(None);
+            }
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(item) => this.current.set(Some((this.f)(item))),
+                None => {
+                    *this.done = true;
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+}
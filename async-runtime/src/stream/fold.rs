@@ -0,0 +1,50 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct Fold<S, B, F> {
+        #[pin]
+        stream: S,
+        // taken out when the stream ends
+        acc: Option<B>,
+        f: F,
+    }
+}
+
+impl<S, B, F> Fold<S, B, F> {
+    pub(super) fn new(stream: S, init: B, f: F) -> Self {
+        Self {
+            stream,
+            acc: Some(init),
+            f,
+        }
+    }
+}
+
+impl<S, B, F> Future for Fold<S, B, F>
+where
+    S: Stream,
+    F: FnMut(B, S::Item) -> B,
+{
+    type Output = B;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(item) => {
+                    let acc = this.acc.take().expect("Fold polled after completion");
+                    *this.acc = Some((this.f)(acc, item));
+                }
+                None => return Poll::Ready(this.acc.take().expect("Fold polled after completion")),
+            }
+        }
+    }
+}
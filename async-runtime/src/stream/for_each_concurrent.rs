@@ -0,0 +1,67 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FuturesUnordered, Stream};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct ForEachConcurrent<S, Fut, F> {
+        #[pin]
+        stream: S,
+        stream_done: bool,
+        // the invocations of f in flight
+        running: FuturesUnordered<Fut>,
+        // None for no limit
+        limit: Option<usize>,
+        f: F,
+    }
+}
+
+impl<S, Fut, F> ForEachConcurrent<S, Fut, F> {
+    pub(super) fn new(stream: S, limit: Option<usize>, f: F) -> Self {
+        assert!(limit != Some(0), "limit must be greater than zero");
+        Self {
+            stream,
+            stream_done: false,
+            running: FuturesUnordered::new(),
+            limit,
+            f,
+        }
+    }
+}
+
+impl<S, Fut, F> Future for ForEachConcurrent<S, Fut, F>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        loop {
+            // start invocations until the limit is reached, with no
+            // backlog: items are only pulled once they can run
+            while !*this.stream_done && this.limit.is_none_or(|limit| this.running.len() < limit) {
+                match this.stream.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(item)) => this.running.push((this.f)(item)),
+                    Poll::Ready(None) => *this.stream_done = true,
+                    Poll::Pending => break,
+                }
+            }
+
+            match Pin::new(&mut *this.running).poll_next(cx) {
+                // a slot freed up, go pull the next item
+                Poll::Ready(Some(())) => continue,
+                Poll::Ready(None) if *this.stream_done => return Poll::Ready(()),
+                // nothing running and the stream is pending, it'll wake us
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
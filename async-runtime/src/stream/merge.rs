@@ -0,0 +1,90 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct Merge<A, B> {
+        #[pin]
+        a: A,
+        #[pin]
+        b: B,
+        a_done: bool,
+        b_done: bool,
+        // which stream gets polled first next time, flipped on every poll so
+        // a busy stream can't starve the other one
+        a_first: bool,
+    }
+}
+
+impl<A, B> Merge<A, B> {
+    pub(super) fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            a_done: false,
+            b_done: false,
+            a_first: true,
+        }
+    }
+}
+
+impl<A, B> Stream for Merge<A, B>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+{
+    type Item = A::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let a_first = *this.a_first;
+        *this.a_first = !a_first;
+
+        if a_first {
+            poll_in_order(this.a, this.a_done, this.b, this.b_done, cx)
+        } else {
+            poll_in_order(this.b, this.b_done, this.a, this.a_done, cx)
+        }
+    }
+}
+
+// Polls `first` then `second`, skipping the ones that already ended. The
+// merged stream only ends once both did.
+fn poll_in_order<X, Y>(
+    first: Pin<&mut X>,
+    first_done: &mut bool,
+    second: Pin<&mut Y>,
+    second_done: &mut bool,
+    cx: &mut Context<'_>,
+) -> Poll<Option<X::Item>>
+where
+    X: Stream,
+    Y: Stream<Item = X::Item>,
+{
+    if !*first_done {
+        match first.poll_next(cx) {
+            Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+            Poll::Ready(None) => *first_done = true,
+            Poll::Pending => {}
+        }
+    }
+
+    if !*second_done {
+        match second.poll_next(cx) {
+            Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+            Poll::Ready(None) => *second_done = true,
+            Poll::Pending => {}
+        }
+    }
+
+    if *first_done && *second_done {
+        Poll::Ready(None)
+    } else {
+        Poll::Pending
+    }
+}
@@ -0,0 +1,327 @@
+//! Extra combinators on top of `futures::Stream`.
+
+mod chunks;
+mod collect;
+mod count;
+mod enumerate;
+mod filter_map;
+mod flatten;
+mod fold;
+mod for_each_concurrent;
+mod inspect;
+mod merge;
+mod next;
+mod peekable;
+mod repeat;
+mod scan;
+mod skip;
+mod take_until;
+mod throttle;
+mod zip;
+
+use std::{future::Future, time::Duration};
+
+use futures::Stream;
+
+pub use chunks::{Chunks, ReadyChunks};
+pub use collect::{Collect, FromStream};
+pub use count::{Count, Last};
+pub use enumerate::Enumerate;
+pub use filter_map::{FilterMap, MapWhile};
+pub use flatten::{FlatMap, Flatten};
+pub use fold::Fold;
+pub use for_each_concurrent::ForEachConcurrent;
+pub use inspect::Inspect;
+pub use merge::Merge;
+pub use next::{Next, TryNext};
+pub use peekable::{Peek, Peekable};
+pub use repeat::{repeat, repeat_with, Repeat, RepeatWith};
+pub use scan::Scan;
+pub use skip::{Skip, StepBy};
+pub use take_until::TakeUntil;
+pub use throttle::{Debounce, Throttle};
+pub use zip::Zip;
+
+pub trait StreamExt: Stream {
+    /// Waits for the next item, `None` once the stream ended. The usual way
+    /// to consume a stream: `while let Some(item) = stream.next().await`.
+    ///
+    /// The stream has to be `Unpin`, pin it first with `Box::pin` or
+    /// `std::pin::pin!` otherwise.
+    fn next(&mut self) -> Next<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Next::new(self)
+    }
+
+    /// Like `next` for a stream of `Result`s, with the error taken out of
+    /// the item: `Ok(None)` once the stream ended, so that `?` can be used
+    /// in the loop: `while let Some(item) = stream.try_next().await?`.
+    fn try_next<T, E>(&mut self) -> TryNext<'_, Self>
+    where
+        Self: Stream<Item = Result<T, E>> + Unpin,
+    {
+        TryNext::new(self)
+    }
+
+    /// Collects `capacity` items into a `Vec` before yielding it. When the
+    /// stream ends in the middle of a batch, the partial batch is yielded as
+    /// the last item.
+    ///
+    /// Panics if `capacity` is zero.
+    fn chunks(self, capacity: usize) -> Chunks<Self>
+    where
+        Self: Sized,
+    {
+        Chunks::new(self, capacity)
+    }
+
+    /// Collects up to `capacity` items that are immediately available,
+    /// without waiting for the batch to fill. Only waits when there is
+    /// nothing to yield at all.
+    ///
+    /// Panics if `capacity` is zero.
+    fn ready_chunks(self, capacity: usize) -> ReadyChunks<Self>
+    where
+        Self: Sized,
+    {
+        ReadyChunks::new(self, capacity)
+    }
+
+    /// Like `Iterator::scan`: `f` gets a mutable reference to a running state
+    /// along with each item and yields a value that may be derived from the
+    /// state. Returning `None` from `f` ends the stream early.
+    fn scan<St, B, F>(self, init: St, f: F) -> Scan<Self, St, F>
+    where
+        Self: Sized,
+        F: FnMut(&mut St, Self::Item) -> Option<B>,
+    {
+        Scan::new(self, init, f)
+    }
+
+    /// Calls `f` with a reference to each item on its way through, e.g. to
+    /// log or count the items, like `Iterator::inspect`.
+    fn inspect<F>(self, f: F) -> Inspect<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item),
+    {
+        Inspect::new(self, f)
+    }
+
+    /// Pairs each item with its index, starting at zero, like
+    /// `Iterator::enumerate`.
+    fn enumerate(self) -> Enumerate<Self>
+    where
+        Self: Sized,
+    {
+        Enumerate::new(self)
+    }
+
+    /// Drops the first `n` items and yields the rest. Ends early if the
+    /// stream ends before `n` items were dropped.
+    fn skip(self, n: usize) -> Skip<Self>
+    where
+        Self: Sized,
+    {
+        Skip::new(self, n)
+    }
+
+    /// Yields the first item and then every `step`-th one after it, like
+    /// `Iterator::step_by`. The items in between are pulled from the stream
+    /// and dropped.
+    ///
+    /// Panics if `step` is zero.
+    fn step_by(self, step: usize) -> StepBy<Self>
+    where
+        Self: Sized,
+    {
+        StepBy::new(self, step)
+    }
+
+    /// Maps each item with `f` and yields the `Some`s, dropping the items
+    /// mapped to `None`, like `Iterator::filter_map`. `f` is a plain
+    /// closure, to await in it use `futures::StreamExt::filter_map`.
+    fn filter_map<B, F>(self, f: F) -> FilterMap<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> Option<B>,
+    {
+        FilterMap::new(self, f)
+    }
+
+    /// Maps each item with `f` and yields the `Some`s until the first
+    /// `None`, which ends the stream, like `Iterator::map_while`. The inner
+    /// stream isn't polled anymore after that.
+    fn map_while<B, F>(self, f: F) -> MapWhile<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> Option<B>,
+    {
+        MapWhile::new(self, f)
+    }
+
+    /// Reduces the stream to a single value, like `Iterator::fold`. The
+    /// returned future resolves once the stream ends.
+    fn fold<B, F>(self, init: B, f: F) -> Fold<Self, B, F>
+    where
+        Self: Sized,
+        F: FnMut(B, Self::Item) -> B,
+    {
+        Fold::new(self, init, f)
+    }
+
+    /// Runs `f` on each item, with up to `limit` of the futures it returns
+    /// running at once, or no limit with `None`. Resolves once the stream
+    /// ended and every future completed, e.g. to serve each incoming
+    /// connection of a listener. The futures run within this one, on its
+    /// task, concurrently but not in parallel: spawn from `f` to use other
+    /// workers. Items are pulled only when there's room for one more
+    /// future, so a slow `f` applies backpressure to the stream.
+    ///
+    /// Panics if `limit` is `Some(0)`.
+    fn for_each_concurrent<Fut, F>(
+        self,
+        limit: impl Into<Option<usize>>,
+        f: F,
+    ) -> ForEachConcurrent<Self, Fut, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        ForEachConcurrent::new(self, limit.into(), f)
+    }
+
+    /// Drains the stream and resolves to the number of items it yielded.
+    /// Never resolves for an infinite stream.
+    fn count(self) -> Count<Self>
+    where
+        Self: Sized,
+    {
+        Count::new(self)
+    }
+
+    /// Drains the stream and resolves to its last item, `None` if it was
+    /// empty. Never resolves for an infinite stream.
+    fn last(self) -> Last<Self>
+    where
+        Self: Sized,
+    {
+        Last::new(self)
+    }
+
+    /// Drains the stream into a collection, like `Iterator::collect`: a
+    /// `Vec`, a map from a stream of pairs, a `String` from a stream of
+    /// `char`s or strings... Collecting a stream of `Result`s into a
+    /// `Result` stops at the first error and resolves to it, the rest of
+    /// the stream isn't polled. Same with `Option`s and the first `None`.
+    fn collect<C>(self) -> Collect<Self, C>
+    where
+        Self: Sized,
+        C: FromStream<Self::Item>,
+    {
+        Collect::new(self)
+    }
+
+    /// Yields the items of both streams as they arrive and ends once both
+    /// ended. When one stream ends first, the merged stream keeps yielding
+    /// from the other. The two are polled first in turn so that neither can
+    /// starve the other.
+    fn merge<St>(self, other: St) -> Merge<Self, St>
+    where
+        Self: Sized,
+        St: Stream<Item = Self::Item>,
+    {
+        Merge::new(self, other)
+    }
+
+    /// Pairs up the items of both streams, yielding `(a, b)` once each
+    /// stream produced its next item. The item of the stream that's ready
+    /// first is buffered until the other one catches up. Ends as soon as
+    /// either stream ends, so with streams of different lengths the extra
+    /// items of the longer one are never pulled, and an item already
+    /// buffered for a pair that can't complete is dropped.
+    fn zip<St>(self, other: St) -> Zip<Self, St>
+    where
+        Self: Sized,
+        St: Stream,
+    {
+        Zip::new(self, other)
+    }
+
+    /// Yields at most one item per `interval`. Items are never dropped: the
+    /// first one comes through right away and each next one is held back
+    /// until `interval` has passed since the previous one, without polling
+    /// the stream in the meantime. The stream ends as soon as the inner one
+    /// does.
+    ///
+    /// To skip the items of a burst rather than slow them down, see
+    /// `debounce`.
+    fn throttle(self, interval: Duration) -> Throttle<Self>
+    where
+        Self: Sized,
+    {
+        Throttle::new(self, interval)
+    }
+
+    /// Yields an item only once the stream has been quiet for `quiet`: an
+    /// item followed by another one within `quiet` is dropped in favor of
+    /// the newer one, so only the last item of each burst comes through.
+    /// When the stream ends, the pending item, if any, is yielded right away
+    /// instead of waiting out the remaining time.
+    fn debounce(self, quiet: Duration) -> Debounce<Self>
+    where
+        Self: Sized,
+    {
+        Debounce::new(self, quiet)
+    }
+
+    /// Yields items until `until` completes, then ends, e.g. to run a loop
+    /// over the stream until a shutdown signal. When an item and `until`
+    /// are ready at the same time, the item is yielded first and the stream
+    /// ends on the next poll.
+    fn take_until<F>(self, until: F) -> TakeUntil<Self, F>
+    where
+        Self: Sized,
+        F: Future,
+    {
+        TakeUntil::new(self, until)
+    }
+
+    /// Buffers one item so that it can be looked at with `Peekable::peek`
+    /// before it's consumed, e.g. for lookahead when parsing frames.
+    fn peekable(self) -> Peekable<Self>
+    where
+        Self: Sized,
+    {
+        Peekable::new(self)
+    }
+
+    /// Yields the items of each inner stream in turn: an inner stream is
+    /// drained to its end before the next one is pulled from the outer
+    /// stream. Errors are items like any other, so with `Result` items an
+    /// error of an inner stream is yielded in place and the inner stream
+    /// keeps being polled after it.
+    fn flatten(self) -> Flatten<Self>
+    where
+        Self: Sized,
+        Self::Item: Stream,
+    {
+        Flatten::new(self)
+    }
+
+    /// Maps each item to a stream with `f` and flattens the result, with
+    /// the same ordering as `flatten`.
+    fn flat_map<U, F>(self, f: F) -> FlatMap<Self, U, F>
+    where
+        Self: Sized,
+        U: Stream,
+        F: FnMut(Self::Item) -> U,
+    {
+        FlatMap::new(self, f)
+    }
+}
+
+impl<S: Stream + ?Sized> StreamExt for S {}
@@ -0,0 +1,81 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{future::FusedFuture, ready, Stream};
+
+/// Future returned by `StreamExt::next`. Dropping it before it completes
+/// loses nothing, no item is taken from the stream until it's returned.
+///
+/// It's terminated once it returned, so a `select!` branch on it doesn't
+/// take a second item from the stream.
+pub struct Next<'a, S: ?Sized> {
+    stream: &'a mut S,
+    done: bool,
+}
+
+impl<'a, S: ?Sized> Next<'a, S> {
+    pub(super) fn new(stream: &'a mut S) -> Self {
+        Self {
+            stream,
+            done: false,
+        }
+    }
+}
+
+impl<S: Stream + Unpin + ?Sized> Future for Next<'_, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // the stream registers cx's waker when it has nothing yet
+        let item = ready!(Pin::new(&mut *self.stream).poll_next(cx));
+        self.done = true;
+        Poll::Ready(item)
+    }
+}
+
+impl<S: Stream + Unpin + ?Sized> FusedFuture for Next<'_, S> {
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+/// Future returned by `StreamExt::try_next`, terminated once it returned
+/// like `Next`.
+pub struct TryNext<'a, S: ?Sized> {
+    stream: &'a mut S,
+    done: bool,
+}
+
+impl<'a, S: ?Sized> TryNext<'a, S> {
+    pub(super) fn new(stream: &'a mut S) -> Self {
+        Self {
+            stream,
+            done: false,
+        }
+    }
+}
+
+impl<S, T, E> Future for TryNext<'_, S>
+where
+    S: Stream<Item = Result<T, E>> + Unpin + ?Sized,
+{
+    type Output = Result<Option<T>, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let item = ready!(Pin::new(&mut *self.stream).poll_next(cx));
+        self.done = true;
+        Poll::Ready(item.transpose())
+    }
+}
+
+impl<S, T, E> FusedFuture for TryNext<'_, S>
+where
+    S: Stream<Item = Result<T, E>> + Unpin + ?Sized,
+{
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct Peekable<S: Stream> {
+        #[pin]
+        stream: S,
+        // taken from the stream by peek, not yielded yet
+        peeked: Option<S::Item>,
+        // the stream ended, it's not polled again
+        done: bool,
+    }
+}
+
+impl<S: Stream> Peekable<S> {
+    pub(super) fn new(stream: S) -> Self {
+        Self {
+            stream,
+            peeked: None,
+            done: false,
+        }
+    }
+
+    /// Waits for the next item and returns a reference to it without
+    /// consuming it, the next poll of the stream yields it. `None` if the
+    /// stream ended.
+    pub fn peek(self: Pin<&mut Self>) -> Peek<'_, S> {
+        Peek { inner: Some(self) }
+    }
+
+    pub fn poll_peek(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<&S::Item>> {
+        let mut this = self.project();
+
+        if this.peeked.is_none() {
+            if *this.done {
+                return Poll::Ready(None);
+            }
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(item) => *this.peeked = Some(item),
+                None => {
+                    *this.done = true;
+                    return Poll::Ready(None);
+                }
+            }
+        }
+
+        Poll::Ready(this.peeked.as_ref())
+    }
+}
+
+impl<S: Stream> Stream for Peekable<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if let Some(item) = this.peeked.take() {
+            return Poll::Ready(Some(item));
+        }
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        let item = ready!(this.stream.poll_next(cx));
+        *this.done = item.is_none();
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            return (0, Some(0));
+        }
+        let peeked = usize::from(self.peeked.is_some());
+        let (lower, upper) = self.stream.size_hint();
+        (
+            lower.saturating_add(peeked),
+            upper.and_then(|upper| upper.checked_add(peeked)),
+        )
+    }
+}
+
+/// Future returned by `Peekable::peek`. Dropping it before it completes
+/// loses nothing, an item it took from the stream stays buffered.
+pub struct Peek<'a, S: Stream> {
+    inner: Option<Pin<&'a mut Peekable<S>>>,
+}
+
+impl<'a, S: Stream> Future for Peek<'a, S> {
+    type Output = Option<&'a S::Item>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut inner = self.inner.take().expect("Peek polled after completion");
+
+        if inner.as_mut().poll_peek(cx).is_pending() {
+            self.inner = Some(inner);
+            return Poll::Pending;
+        }
+
+        Poll::Ready(inner.project().peeked.as_ref())
+    }
+}
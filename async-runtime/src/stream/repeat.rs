@@ -0,0 +1,61 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+
+/// Creates a stream yielding clones of `value` forever. Bound it with
+/// `futures::StreamExt::take`, or `take_until`.
+pub fn repeat<T: Clone>(value: T) -> Repeat<T> {
+    Repeat { value }
+}
+
+/// Creates a stream yielding whatever `f` returns, forever. `f` is called
+/// once per item so it can keep state, e.g. to produce a sequence.
+pub fn repeat_with<T, F>(f: F) -> RepeatWith<F>
+where
+    F: FnMut() -> T,
+{
+    RepeatWith { f }
+}
+
+pub struct Repeat<T> {
+    value: T,
+}
+
+// the value is never pinned
+impl<T> Unpin for Repeat<T> {}
+
+impl<T: Clone> Stream for Repeat<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(Some(self.value.clone()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+pub struct RepeatWith<F> {
+    f: F,
+}
+
+impl<F> Unpin for RepeatWith<F> {}
+
+impl<T, F> Stream for RepeatWith<F>
+where
+    F: FnMut() -> T,
+{
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(Some((self.f)()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct Scan<S, St, F> {
+        #[pin]
+        stream: S,
+        state: St,
+        f: F,
+        done: bool,
+    }
+}
+
+impl<S, St, F> Scan<S, St, F> {
+    pub(super) fn new(stream: S, state: St, f: F) -> Self {
+        Self {
+            stream,
+            state,
+            f,
+            done: false,
+        }
+    }
+}
+
+impl<S, St, B, F> Stream for Scan<S, St, F>
+where
+    S: Stream,
+    F: FnMut(&mut St, S::Item) -> Option<B>,
+{
+    type Item = B;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        let item = ready!(this.stream.poll_next(cx)).and_then(|item| (this.f)(this.state, item));
+        if item.is_none() {
+            *this.done = true;
+        }
+
+        Poll::Ready(item)
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct Skip<S> {
+        #[pin]
+        stream: S,
+        // items left to drop before yielding
+        remaining: usize,
+    }
+}
+
+impl<S> Skip<S> {
+    pub(super) fn new(stream: S, n: usize) -> Self {
+        Self {
+            stream,
+            remaining: n,
+        }
+    }
+}
+
+impl<S: Stream> Stream for Skip<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        while *this.remaining > 0 {
+            if ready!(this.stream.as_mut().poll_next(cx)).is_none() {
+                return Poll::Ready(None);
+            }
+            *this.remaining -= 1;
+        }
+
+        this.stream.poll_next(cx)
+    }
+}
+
+pin_project! {
+    pub struct StepBy<S> {
+        #[pin]
+        stream: S,
+        // items dropped between two yielded ones
+        gap: usize,
+        // items left to drop before the next yielded one, none for the first
+        remaining: usize,
+    }
+}
+
+impl<S> StepBy<S> {
+    pub(super) fn new(stream: S, step: usize) -> Self {
+        assert!(step > 0, "step must be greater than zero");
+        Self {
+            stream,
+            gap: step - 1,
+            remaining: 0,
+        }
+    }
+}
+
+impl<S: Stream> Stream for StepBy<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            let Some(item) = ready!(this.stream.as_mut().poll_next(cx)) else {
+                return Poll::Ready(None);
+            };
+
+            if *this.remaining == 0 {
+                *this.remaining = *this.gap;
+                return Poll::Ready(Some(item));
+            }
+            *this.remaining -= 1;
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct TakeUntil<S, F> {
+        #[pin]
+        stream: S,
+        // dropped once it completes
+        #[pin]
+        until: Option<F>,
+        done: bool,
+    }
+}
+
+impl<S, F> TakeUntil<S, F> {
+    pub(super) fn new(stream: S, until: F) -> Self {
+        Self {
+            stream,
+            until: Some(until),
+            done: false,
+        }
+    }
+}
+
+impl<S, F> Stream for TakeUntil<S, F>
+where
+    S: Stream,
+    F: Future,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        if let Some(until) = this.until.as_mut().as_pin_mut() {
+            if until.poll(cx).is_ready() {
+                this.until.set(None);
+            }
+        }
+        let stopped = this.until.is_none();
+
+        match this.stream.poll_next(cx) {
+            // an item that was ready along with the terminator still makes
+            // it through, the stream ends on the next poll
+            Poll::Ready(Some(item)) => {
+                *this.done = stopped;
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(None) => {
+                *this.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending if stopped => {
+                *this.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{ready, Stream};
+use pin_project_lite::pin_project;
+
+use crate::time::{sleep, Sleep};
+
+pin_project! {
+    pub struct Throttle<S> {
+        #[pin]
+        stream: S,
+        interval: Duration,
+        // running while the next item has to wait
+        delay: Option<Sleep>,
+    }
+}
+
+impl<S> Throttle<S> {
+    pub(super) fn new(stream: S, interval: Duration) -> Self {
+        Self {
+            stream,
+            interval,
+            delay: None,
+        }
+    }
+}
+
+impl<S: Stream> Stream for Throttle<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if let Some(delay) = this.delay {
+            ready!(Pin::new(delay).poll(cx));
+            *this.delay = None;
+        }
+
+        let item = ready!(this.stream.poll_next(cx));
+        if item.is_some() {
+            *this.delay = Some(sleep(*this.interval));
+        }
+
+        Poll::Ready(item)
+    }
+}
+
+pin_project! {
+    pub struct Debounce<S: Stream> {
+        #[pin]
+        stream: S,
+        quiet: Duration,
+        // the latest item and the timer it's waiting on
+        pending: Option<(S::Item, Sleep)>,
+        done: bool,
+    }
+}
+
+impl<S: Stream> Debounce<S> {
+    pub(super) fn new(stream: S, quiet: Duration) -> Self {
+        Self {
+            stream,
+            quiet,
+            pending: None,
+            done: false,
+        }
+    }
+}
+
+impl<S: Stream> Stream for Debounce<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        while !*this.done {
+            match this.stream.as_mut().poll_next(cx) {
+                // a newer item replaces the pending one and restarts the wait
+                Poll::Ready(Some(item)) => *this.pending = Some((item, sleep(*this.quiet))),
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        let Some((_, quiet)) = this.pending else {
+            return if *this.done {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            };
+        };
+
+        // the stream ended, no need to wait for it to be quiet
+        if !*this.done {
+            ready!(Pin::new(quiet).poll(cx));
+        }
+
+        Poll::Ready(this.pending.take().map(|(item, _)| item))
+    }
+}
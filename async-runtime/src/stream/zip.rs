@@ -0,0 +1,75 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+pin_project! {
+    pub struct Zip<A: Stream, B: Stream> {
+        #[pin]
+        a: A,
+        #[pin]
+        b: B,
+        // the item of the stream that was ready first, waiting for its pair
+        a_item: Option<A::Item>,
+        b_item: Option<B::Item>,
+        // set once either stream ended, neither is polled again after that
+        done: bool,
+    }
+}
+
+impl<A: Stream, B: Stream> Zip<A, B> {
+    pub(super) fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            a_item: None,
+            b_item: None,
+            done: false,
+        }
+    }
+}
+
+impl<A: Stream, B: Stream> Stream for Zip<A, B> {
+    type Item = (A::Item, B::Item);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        if this.a_item.is_none() {
+            match this.a.poll_next(cx) {
+                Poll::Ready(Some(item)) => *this.a_item = Some(item),
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => {}
+            }
+        }
+
+        if !*this.done && this.b_item.is_none() {
+            match this.b.poll_next(cx) {
+                Poll::Ready(Some(item)) => *this.b_item = Some(item),
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => {}
+            }
+        }
+
+        if *this.done {
+            // the item buffered from the longer stream has no pair left and
+            // is dropped right away rather than along with the stream
+            this.a_item.take();
+            this.b_item.take();
+            return Poll::Ready(None);
+        }
+
+        if this.a_item.is_some() && this.b_item.is_some() {
+            Poll::Ready(this.a_item.take().zip(this.b_item.take()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
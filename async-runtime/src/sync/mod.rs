@@ -0,0 +1,20 @@
+//! Synchronization primitives for tasks.
+
+// TODO deadlock detection, behind a debug feature: record which task holds
+// each async Mutex/RwLock and which tasks wait on it, and log the task ids
+// of any wait-for cycle. There's no async Mutex or RwLock to track yet, only
+// Semaphore, whose permits have no owner to build a wait-for graph from.
+// Once the locks exist, they can note the holder's TaskId on acquire and
+// the waiter's in their queue, and look the tasks up in the registry.
+
+// TODO adaptive batching for an async mpsc Receiver: after the first
+// awaited recv, drain whatever is already queued with try_recv, growing the
+// batch size while batches come back full and shrinking it when they don't,
+// and report the average batch size in RuntimeMetrics. There's no async mpsc
+// channel in this crate to build it on yet; the runtime only uses crossbeam
+// channels internally. Until there is, StreamExt::ready_chunks over any
+// receiver stream gives the non-adaptive version.
+
+mod semaphore;
+
+pub use semaphore::{Acquire, OwnedSemaphorePermit, Semaphore, SemaphorePermit};
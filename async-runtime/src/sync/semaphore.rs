@@ -0,0 +1,190 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use crate::task::{with_wake_source, AwaitSite, WakeSource};
+
+/// An async counting semaphore. Waiters get permits in the order they
+/// started waiting, a released permit goes straight to the oldest waiter so
+/// that newcomers can't barge in front of it.
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+struct State {
+    permits: usize,
+    waiters: VecDeque<Arc<Waiter>>,
+}
+
+struct Waiter {
+    // set once a released permit has been handed to this waiter
+    granted: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                permits,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().permits
+    }
+
+    /// Waits for a permit, released when the returned guard is dropped.
+    /// Cancel safe: dropping the future gives up its place in the queue, or
+    /// the permit if it was granted meanwhile.
+    #[track_caller]
+    pub fn acquire(&self) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            waiter: None,
+            site: AwaitSite::new("semaphore acquire"),
+        }
+    }
+
+    /// Takes a permit if one is available and nobody is waiting for it.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if state.permits > 0 && state.waiters.is_empty() {
+            state.permits -= 1;
+            Some(SemaphorePermit { semaphore: self })
+        } else {
+            None
+        }
+    }
+
+    /// Same as `acquire` but the permit holds on to the semaphore, so it can
+    /// be moved into another task or thread.
+    pub async fn acquire_owned(self: Arc<Self>) -> OwnedSemaphorePermit {
+        self.acquire().await.forget();
+        OwnedSemaphorePermit { semaphore: self }
+    }
+
+    /// Adds `permits` permits, waking up as many waiters.
+    pub fn add_permits(&self, permits: usize) {
+        for _ in 0..permits {
+            self.release();
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        match state.waiters.pop_front() {
+            Some(waiter) => {
+                waiter.granted.store(true, Ordering::Release);
+                drop(state);
+                if let Some(waker) = waiter.waker.lock().unwrap().take() {
+                    with_wake_source(WakeSource::Sync, || waker.wake());
+                }
+            }
+            None => state.permits += 1,
+        }
+    }
+}
+
+/// Future returned by `Semaphore::acquire`.
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    // queued up, waiting for a permit
+    waiter: Option<Arc<Waiter>>,
+    site: AwaitSite,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let semaphore = self.semaphore;
+
+        if let Some(waiter) = &self.waiter {
+            *waiter.waker.lock().unwrap() = Some(cx.waker().clone());
+            // checked after the waker is stored so that a grant in between
+            // isn't missed
+            if waiter.granted.load(Ordering::Acquire) {
+                self.waiter = None;
+                return Poll::Ready(SemaphorePermit { semaphore });
+            }
+            self.site.pending();
+            return Poll::Pending;
+        }
+
+        let mut state = semaphore.state.lock().unwrap();
+        if state.permits > 0 && state.waiters.is_empty() {
+            state.permits -= 1;
+            return Poll::Ready(SemaphorePermit { semaphore });
+        }
+
+        let waiter = Arc::new(Waiter {
+            granted: AtomicBool::new(false),
+            waker: Mutex::new(Some(cx.waker().clone())),
+        });
+        state.waiters.push_back(waiter.clone());
+        drop(state);
+        self.waiter = Some(waiter);
+
+        self.site.pending();
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let Some(waiter) = self.waiter.take() else {
+            return;
+        };
+
+        let mut state = self.semaphore.state.lock().unwrap();
+        // granted is only set under the lock, so it can't change here
+        if waiter.granted.load(Ordering::Acquire) {
+            drop(state);
+            // pass the permit on
+            self.semaphore.release();
+        } else {
+            state.waiters.retain(|other| !Arc::ptr_eq(other, &waiter));
+        }
+    }
+}
+
+/// A permit of a `Semaphore`, given back when dropped.
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl SemaphorePermit<'_> {
+    /// Keeps the permit taken for good.
+    pub fn forget(self) {
+        mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
+
+/// A permit of a `Semaphore` that owns a reference to it, see
+/// `Semaphore::acquire_owned`.
+pub struct OwnedSemaphorePermit {
+    semaphore: Arc<Semaphore>,
+}
+
+impl Drop for OwnedSemaphorePermit {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
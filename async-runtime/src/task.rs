@@ -0,0 +1,293 @@
+//! Working with the current task and the live tasks of the runtime.
+
+#[cfg(any(feature = "wake-sources", feature = "tracing"))]
+use std::cell::Cell;
+#[cfg(feature = "wake-sources")]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+#[cfg(feature = "await-locations")]
+use std::{fmt::Display, panic::Location};
+
+#[cfg(feature = "await-locations")]
+use crate::runtime::set_current_await_point;
+use crate::runtime::with_current_extensions;
+#[cfg(feature = "task-registry")]
+use crate::runtime::{current, TaskId};
+
+/// A map holding at most one value of each type, attached to a task, see
+/// `extensions`.
+#[derive(Default)]
+pub struct Extensions {
+    // not allocated until something is inserted, most tasks have none
+    map: Option<HashMap<TypeId, Box<dyn Any + Send>>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts `value`, returning the previous value of the same type.
+    pub fn insert<T: Send + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .get_or_insert_with(Default::default)
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .map(|previous| *previous.downcast().unwrap())
+    }
+
+    pub fn get<T: Send + 'static>(&self) -> Option<&T> {
+        self.map.as_ref()?.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    pub fn get_mut<T: Send + 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .as_mut()?
+            .get_mut(&TypeId::of::<T>())?
+            .downcast_mut()
+    }
+
+    pub fn remove<T: Send + 'static>(&mut self) -> Option<T> {
+        let value = self.map.as_mut()?.remove(&TypeId::of::<T>())?;
+        Some(*value.downcast().unwrap())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.as_ref().is_none_or(|map| map.is_empty())
+    }
+}
+
+/// Runs `f` with the extensions of the task being polled, so that layers of
+/// middleware can attach data, e.g. an auth context or a deadline, for the
+/// inner code to read without global state. Panics outside of a task, and
+/// when called from within `f`.
+///
+/// Extensions belong to one task: a task spawned from it starts with none,
+/// copy what it needs over with `Handle::spawn_with_extensions`.
+pub fn extensions<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Extensions) -> R,
+{
+    try_extensions(f).expect("task extensions accessed outside of a task")
+}
+
+/// Same as `extensions` but returns `None` outside of a task.
+pub fn try_extensions<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut Extensions) -> R,
+{
+    with_current_extensions(f)
+}
+
+/// Identifies the request, or whatever unit of work, a task is part of, so
+/// that its logs can be correlated across tasks and threads. Attached to the
+/// task with `set_trace_id`, read back anywhere down the line with
+/// `trace_id`.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId(pub u64);
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    // the trace id of the blocking task running on this thread
+    static BLOCKING_TRACE_ID: Cell<Option<TraceId>> = const { Cell::new(None) };
+}
+
+/// Attaches `id` to the task being polled, it's stored in its extensions.
+/// Panics outside of a task.
+#[cfg(feature = "tracing")]
+pub fn set_trace_id(id: TraceId) {
+    extensions(|extensions| extensions.insert(id));
+}
+
+/// The trace id of the task being polled or, on a blocking thread, of the
+/// task that called `Handle::spawn_blocking`, which inherits it.
+#[cfg(feature = "tracing")]
+pub fn trace_id() -> Option<TraceId> {
+    try_extensions(|extensions| extensions.get::<TraceId>().copied())
+        .unwrap_or_else(|| BLOCKING_TRACE_ID.with(Cell::get))
+}
+
+/// Runs the blocking task `f` with `id` as the current trace id of the
+/// thread, see `trace_id`.
+#[cfg(feature = "tracing")]
+pub(crate) fn with_trace_id<F, R>(id: Option<TraceId>, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    // the pool thread is reused, don't leak the id to the next task even if
+    // f panics
+    struct Restore(Option<TraceId>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            BLOCKING_TRACE_ID.with(|current| current.set(self.0));
+        }
+    }
+
+    let _restore = Restore(BLOCKING_TRACE_ID.with(|current| current.replace(id)));
+    f()
+}
+
+/// Suspends the task, see `AbortHandle::suspend`. Returns false if there's
+/// no such live task.
+#[cfg(feature = "task-registry")]
+pub fn suspend(id: TaskId) -> bool {
+    current().task(id).map(|task| task.suspend()).is_some()
+}
+
+/// Resumes the task, see `AbortHandle::resume`. Returns false if there's no
+/// such live task.
+#[cfg(feature = "task-registry")]
+pub fn resume(id: TaskId) -> bool {
+    current().task(id).map(|task| task.resume()).is_some()
+}
+
+/// What woke a task, see `with_wake_source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeSource {
+    /// A deadline of `time::sleep` and friends.
+    Timer,
+    /// A value or a completion delivered through a channel, e.g. to a
+    /// `JoinHandle` or a `JoinSet`.
+    Channel,
+    /// An I/O resource such as a `io::duplex` pipe.
+    Io,
+    /// A synchronization primitive such as `sync::Semaphore`.
+    Sync,
+    /// Anything that didn't label its wakes, e.g. a waker called by hand or
+    /// by a foreign library.
+    Manual,
+}
+
+#[cfg(feature = "wake-sources")]
+thread_local! {
+    static WAKE_SOURCE: Cell<WakeSource> = const { Cell::new(WakeSource::Manual) };
+}
+
+/// Attributes the wakes done by `f` to `source`, e.g. around the loop of a
+/// channel waking its receivers. Wakers that go through combinators before
+/// reaching the task still count since the label is kept per thread. Only
+/// recorded with the `wake-sources` feature, otherwise this just runs `f`.
+pub fn with_wake_source<F, R>(source: WakeSource, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    #[cfg(feature = "wake-sources")]
+    {
+        // restores the outer label even if f panics
+        struct Restore(WakeSource);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                WAKE_SOURCE.with(|current| current.set(self.0));
+            }
+        }
+
+        let _restore = Restore(WAKE_SOURCE.with(|current| current.replace(source)));
+        f()
+    }
+
+    #[cfg(not(feature = "wake-sources"))]
+    {
+        let _ = source;
+        f()
+    }
+}
+
+/// How many times a task was woken by each source, see
+/// `Handle::task_stats`.
+#[cfg(feature = "wake-sources")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WakeCounts {
+    pub timer: u64,
+    pub channel: u64,
+    pub io: u64,
+    pub sync: u64,
+    pub manual: u64,
+}
+
+#[cfg(feature = "wake-sources")]
+impl WakeCounts {
+    pub fn total(&self) -> u64 {
+        self.timer + self.channel + self.io + self.sync + self.manual
+    }
+}
+
+/// The live counterpart of `WakeCounts`, one per task.
+#[cfg(feature = "wake-sources")]
+#[derive(Default)]
+pub(crate) struct WakeCounters([AtomicU64; 5]);
+
+#[cfg(feature = "wake-sources")]
+impl WakeCounters {
+    /// Counts one wake from whatever source the current thread is labeled
+    /// with.
+    pub(crate) fn record(&self) {
+        let source = WAKE_SOURCE.with(Cell::get);
+        self.0[source as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> WakeCounts {
+        let count = |source: WakeSource| self.0[source as usize].load(Ordering::Relaxed);
+        WakeCounts {
+            timer: count(WakeSource::Timer),
+            channel: count(WakeSource::Channel),
+            io: count(WakeSource::Io),
+            sync: count(WakeSource::Sync),
+            manual: count(WakeSource::Manual),
+        }
+    }
+}
+
+/// A future of the crate a task is suspended at, see `Handle::task_stats`.
+#[cfg(feature = "await-locations")]
+#[derive(Debug, Clone, Copy)]
+pub struct AwaitPoint {
+    /// What the future waits for, e.g. `sleep` or `read_exact`.
+    pub what: &'static str,
+    /// Where the future was created, usually right where it's awaited.
+    pub location: &'static Location<'static>,
+}
+
+#[cfg(feature = "await-locations")]
+impl Display for AwaitPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.what, self.location)
+    }
+}
+
+/// Held by the leaf futures of the crate to report where the task is
+/// suspended when they return `Poll::Pending`. Empty without the
+/// `await-locations` feature.
+#[derive(Clone, Copy)]
+pub(crate) struct AwaitSite {
+    #[cfg(feature = "await-locations")]
+    point: AwaitPoint,
+}
+
+impl AwaitSite {
+    /// Captures the location of the caller, so every function in between
+    /// and the user code must be `#[track_caller]` too.
+    #[track_caller]
+    pub(crate) fn new(what: &'static str) -> Self {
+        #[cfg(not(feature = "await-locations"))]
+        let _ = what;
+
+        Self {
+            #[cfg(feature = "await-locations")]
+            point: AwaitPoint {
+                what,
+                location: Location::caller(),
+            },
+        }
+    }
+
+    /// Records the site as the await point of the task being polled.
+    pub(crate) fn pending(&self) {
+        #[cfg(feature = "await-locations")]
+        set_current_await_point(self.point);
+    }
+}
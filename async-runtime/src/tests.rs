@@ -0,0 +1,2652 @@
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use futures::{
+        executor::{block_on, block_on_stream},
+        future::{self, FutureExt},
+        stream, AsyncReadExt, AsyncWriteExt,
+    };
+
+    use crate::{
+        codec::{Framed, LengthDelimitedCodec, LinesCodec},
+        health::*,
+        heartbeat::TaskState,
+        io::duplex,
+        join_set::{JoinSet, OrderedJoinSet},
+        metrics::RuntimeMetrics,
+        runtime::*,
+        stream::StreamExt,
+        time::{sleep, sleep_until, timeout},
+    };
+
+    // yields `items` then ends, and panics if polled again after its end
+    fn unfused<T>(items: Vec<T>) -> impl futures::Stream<Item = T> + Unpin {
+        let mut items = items.into_iter();
+        let mut ended = false;
+        stream::poll_fn(move |_| {
+            assert!(!ended, "stream polled after its end");
+            let item = items.next();
+            ended = item.is_none();
+            std::task::Poll::Ready(item)
+        })
+    }
+
+    #[test]
+    fn test_random_differs_between_threads() {
+        use crate::util::random;
+
+        let draw = || (0..16).map(|_| random(1 << 16)).collect::<Vec<_>>();
+        let first = std::thread::spawn(draw).join().unwrap();
+        let second = std::thread::spawn(draw).join().unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_abort_unblocks_join() {
+        let runtime = new_runtime(1, 1);
+
+        let handle = runtime.spawn(futures::future::pending::<()>());
+        handle.abort();
+
+        assert!(matches!(handle.join(), Err(JoinError::Cancelled)));
+    }
+
+    #[test]
+    fn test_abort_after_completion() {
+        let runtime = new_runtime(1, 1);
+
+        let handle = runtime.spawn(async { 1 });
+        let abort_handle = handle.abort_handle().unwrap();
+
+        assert_eq!(handle.join().unwrap(), 1);
+        abort_handle.abort();
+    }
+
+    #[test]
+    fn test_chunks_flushes_partial_chunk() {
+        let chunks: Vec<_> = block_on_stream(stream::iter(1..=5).chunks(2)).collect();
+
+        assert_eq!(chunks, vec![vec![1, 2], vec![3, 4], vec![5]]);
+    }
+
+    #[test]
+    fn test_ready_chunks_does_not_wait() {
+        let (send, recv) = futures::channel::mpsc::unbounded();
+        let mut chunks = block_on_stream(recv.ready_chunks(2));
+
+        send.unbounded_send(1).unwrap();
+        assert_eq!(chunks.next(), Some(vec![1]));
+
+        for i in 2..=4 {
+            send.unbounded_send(i).unwrap();
+        }
+        drop(send);
+
+        assert_eq!(chunks.next(), Some(vec![2, 3]));
+        assert_eq!(chunks.next(), Some(vec![4]));
+        assert_eq!(chunks.next(), None);
+    }
+
+    #[test]
+    fn test_scan_running_sum_and_early_end() {
+        let sums = stream::iter(1..=10).scan(0, |sum, x| {
+            *sum += x;
+            (*sum < 10).then_some(*sum)
+        });
+
+        assert_eq!(block_on_stream(sums).collect::<Vec<_>>(), vec![1, 3, 6]);
+    }
+
+    #[test]
+    fn test_enumerate_skip_step_by() {
+        let items: Vec<_> =
+            block_on_stream(StreamExt::enumerate(stream::iter(["a", "b", "c"]))).collect();
+        assert_eq!(items, vec![(0, "a"), (1, "b"), (2, "c")]);
+
+        let items: Vec<_> = block_on_stream(StreamExt::skip(stream::iter(0..5), 3)).collect();
+        assert_eq!(items, vec![3, 4]);
+        let items: Vec<_> = block_on_stream(StreamExt::skip(stream::iter(0..2), 3)).collect();
+        assert!(items.is_empty());
+
+        let items: Vec<_> = block_on_stream(StreamExt::step_by(stream::iter(0..10), 3)).collect();
+        assert_eq!(items, (0..10).step_by(3).collect::<Vec<_>>());
+        let items: Vec<_> = block_on_stream(StreamExt::step_by(stream::iter(0..3), 1)).collect();
+        assert_eq!(items, vec![0, 1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "step must be greater than zero")]
+    fn test_step_by_zero() {
+        let _ = StreamExt::step_by(stream::iter(0..3), 0);
+    }
+
+    #[test]
+    fn test_repeat() {
+        use futures::StreamExt as _;
+
+        use crate::stream::{repeat, repeat_with};
+
+        let items: Vec<_> = block_on_stream(repeat("a").take(3)).collect();
+        assert_eq!(items, vec!["a", "a", "a"]);
+
+        let mut next = 1;
+        let powers = repeat_with(move || {
+            let power = next;
+            next *= 2;
+            power
+        });
+        let items: Vec<_> = block_on_stream(powers.take(5)).collect();
+        assert_eq!(items, vec![1, 2, 4, 8, 16]);
+    }
+
+    #[test]
+    fn test_inspect() {
+        let mut seen = Vec::new();
+        let items: Vec<_> =
+            block_on_stream(StreamExt::inspect(stream::iter(1..=3), |n| seen.push(*n))).collect();
+        assert_eq!(items, [1, 2, 3]);
+        assert_eq!(seen, [1, 2, 3]);
+
+        let inspected = StreamExt::inspect(stream::iter(1..=3), |_| ());
+        assert_eq!(futures::Stream::size_hint(&inspected), (3, Some(3)));
+    }
+
+    #[test]
+    fn test_filter_map_and_map_while() {
+        let lines = ["1", "", "2", "end", "3"];
+
+        let numbers: Vec<u32> =
+            block_on_stream(StreamExt::filter_map(stream::iter(lines), |line| {
+                line.parse().ok()
+            }))
+            .collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+
+        // the sentinel ends the stream, what's after it is never parsed
+        let numbers: Vec<u32> = block_on_stream(StreamExt::map_while(
+            stream::iter(lines.into_iter().filter(|line| !line.is_empty())),
+            |line| line.parse().ok(),
+        ))
+        .collect();
+        assert_eq!(numbers, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_fold() {
+        let sum = block_on(stream::iter(1..=4).fold(0, |acc, x| acc + x));
+
+        assert_eq!(sum, 10);
+    }
+
+    #[test]
+    fn test_health_assessment() {
+        let thresholds = HealthThresholds {
+            degraded_queue_depth: 10,
+            unhealthy_queue_depth: 100,
+            degraded_queue_growth: 5,
+            unhealthy_queue_growth: 50,
+            trend_samples: 3,
+            degraded_parked_workers: 2,
+            degraded_dead_workers: 1,
+            unhealthy_dead_workers: 3,
+        };
+        let metrics = |live_workers, parked_workers, global_queue_depth| RuntimeMetrics {
+            live_workers,
+            parked_workers,
+            global_queue_depth,
+            ..Default::default()
+        };
+        let assess = |metrics, queue_depths: &[usize]| {
+            HealthStatus::assess(&metrics, queue_depths, 4, &thresholds)
+        };
+
+        assert_eq!(assess(metrics(4, 0, 0), &[0]), HealthStatus::Healthy);
+        assert_eq!(assess(metrics(4, 0, 10), &[10]), HealthStatus::Degraded);
+        assert_eq!(assess(metrics(4, 0, 100), &[100]), HealthStatus::Unhealthy);
+
+        // the trend, below the depth thresholds
+        assert_eq!(assess(metrics(4, 0, 4), &[1, 2, 4]), HealthStatus::Healthy);
+        assert_eq!(assess(metrics(4, 0, 6), &[1, 3, 6]), HealthStatus::Degraded);
+        assert_eq!(
+            assess(metrics(4, 0, 6), &[6, 3, 1, 6]),
+            HealthStatus::Healthy
+        );
+        let thresholds = HealthThresholds {
+            degraded_queue_depth: 1000,
+            unhealthy_queue_depth: 1000,
+            ..thresholds
+        };
+        let status = HealthStatus::assess(&metrics(4, 0, 60), &[5, 20, 60], 4, &thresholds);
+        assert_eq!(status, HealthStatus::Unhealthy);
+
+        // parked workers only count with a lasting backlog
+        assert_eq!(assess(metrics(4, 2, 1), &[1, 1, 1]), HealthStatus::Degraded);
+        assert_eq!(assess(metrics(4, 2, 1), &[0, 1, 1]), HealthStatus::Healthy);
+        assert_eq!(assess(metrics(4, 2, 1), &[1, 1]), HealthStatus::Healthy);
+        assert_eq!(assess(metrics(4, 1, 1), &[1, 1, 1]), HealthStatus::Healthy);
+
+        assert_eq!(assess(metrics(3, 0, 0), &[0]), HealthStatus::Degraded);
+        assert_eq!(assess(metrics(1, 0, 0), &[0]), HealthStatus::Unhealthy);
+        let status = HealthStatus::assess(&metrics(0, 0, 0), &[0], 0, &thresholds);
+        assert_eq!(status, HealthStatus::Unhealthy);
+    }
+
+    #[test]
+    fn test_health_queue_depth_trend() {
+        let runtime = Builder::new()
+            .worker_threads(1)
+            .max_blocking_threads(1)
+            .health_thresholds(HealthThresholds {
+                degraded_queue_growth: 2,
+                trend_samples: 3,
+                ..Default::default()
+            })
+            .build();
+
+        // keep the only worker busy so that the queue only grows
+        let (release_send, release_recv) = std::sync::mpsc::channel::<()>();
+        let (busy_send, busy_recv) = std::sync::mpsc::channel();
+        runtime.spawn(async move {
+            busy_send.send(()).unwrap();
+            release_recv.recv().unwrap();
+        });
+        busy_recv.recv().unwrap();
+        assert_eq!(runtime.health(), HealthStatus::Healthy);
+
+        let mut handles = vec![runtime.spawn(async {})];
+        assert_eq!(runtime.health(), HealthStatus::Healthy);
+        handles.push(runtime.spawn(async {}));
+        assert_eq!(runtime.health(), HealthStatus::Degraded);
+
+        release_send.send(()).unwrap();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        // the samples from the backlog age out
+        runtime.health();
+        runtime.health();
+        assert_eq!(runtime.health(), HealthStatus::Healthy);
+    }
+
+    #[test]
+    fn test_blocking_pools() {
+        let runtime = new_runtime(1, 1);
+
+        assert!(runtime.blocking_pool("disk", 1));
+        assert!(!runtime.blocking_pool("disk", 4));
+        assert!(runtime.blocking_pool("compression", 1));
+
+        // hog the default pool and the disk pool
+        let (release_send, release_recv) = crossbeam_channel::bounded::<()>(0);
+        let hogs: Vec<_> = [None, Some("disk"), Some("disk")]
+            .into_iter()
+            .map(|pool| {
+                let release_recv = release_recv.clone();
+                let hog = move || release_recv.recv().unwrap();
+                match pool {
+                    Some(name) => runtime.spawn_blocking_in(name, hog),
+                    None => runtime.spawn_blocking(hog),
+                }
+            })
+            .collect();
+
+        // compression still has its own thread
+        let compressed = runtime.spawn_blocking_in("compression", || 42);
+        assert_eq!(compressed.join().unwrap(), 42);
+
+        let disk = loop {
+            let metrics = runtime.metrics();
+            let disk = metrics.blocking_pools[1].clone();
+            if disk.busy_threads == 1 {
+                break disk;
+            }
+            std::thread::yield_now();
+        };
+        let names: Vec<_> = runtime
+            .metrics()
+            .blocking_pools
+            .into_iter()
+            .map(|pool| pool.name)
+            .collect();
+        assert_eq!(names, ["compression", "disk"]);
+        assert_eq!(disk.threads, 1);
+        assert_eq!(disk.queue_depth, 1);
+
+        for _ in 0..hogs.len() {
+            release_send.send(()).unwrap();
+        }
+        for hog in hogs {
+            hog.join().unwrap();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "no blocking pool named \"network\"")]
+    fn test_spawn_blocking_in_unknown_pool() {
+        let runtime = new_runtime(1, 1);
+        runtime.spawn_blocking_in("network", || ());
+    }
+
+    #[test]
+    fn test_abort_pending_blocking() {
+        // a single blocking thread besides the worker
+        let runtime = new_runtime(1, 1);
+
+        let (started_send, started_recv) = crossbeam_channel::bounded(1);
+        let (release_send, release_recv) = crossbeam_channel::bounded::<()>(1);
+
+        let running = runtime.spawn_blocking(move || {
+            started_send.send(()).unwrap();
+            release_recv.recv().unwrap();
+            1
+        });
+        started_recv.recv().unwrap();
+
+        let pending = runtime.spawn_blocking(|| 2);
+
+        assert_eq!(runtime.abort_pending_blocking(), 1);
+        assert!(matches!(pending.join(), Err(JoinError::Cancelled)));
+
+        release_send.send(()).unwrap();
+        assert_eq!(running.join().unwrap(), 1);
+
+        // the worker survives and still runs tasks
+        assert_eq!(runtime.block_on(async { 3 }), 3);
+    }
+
+    #[test]
+    fn test_select_biased() {
+        let winner = block_on(async {
+            crate::select! {
+                biased;
+                a = future::ready(1).fuse() => a,
+                b = future::ready(2).fuse() => b,
+            }
+        });
+
+        assert_eq!(winner, 1);
+    }
+
+    #[test]
+    fn test_select_loop_until_complete() {
+        let sum = block_on(async {
+            let mut a = future::ready(1).fuse();
+            let mut b = future::ready(2).fuse();
+            let mut sum = 0;
+
+            loop {
+                crate::select! {
+                    x = &mut a => sum += x,
+                    x = &mut b => sum += x,
+                    complete => break,
+                }
+            }
+
+            sum
+        });
+
+        assert_eq!(sum, 3);
+    }
+
+    #[test]
+    fn test_select_else_and_pattern() {
+        let result = block_on(async {
+            crate::select! {
+                _ = future::pending::<()>().fuse() => "pending",
+                else => "else",
+            }
+        });
+        assert_eq!(result, "else");
+
+        // a branch whose pattern doesn't match is disabled
+        let result = block_on(async {
+            crate::select! {
+                biased;
+                Some(x) = future::ready(None::<i32>).fuse() => x,
+                x = future::ready(2).fuse() => x,
+            }
+        });
+        assert_eq!(result, 2);
+    }
+
+    #[test]
+    fn test_try_join() {
+        let runtime = new_runtime(1, 1);
+
+        runtime.block_on(async {
+            let ok: Result<_, &str> = crate::try_join!(async { Ok(1) }, async {
+                sleep(Duration::from_millis(10)).await;
+                Ok("two")
+            },);
+            assert_eq!(ok, Ok((1, "two")));
+
+            // the pending branch is dropped as soon as the other one fails
+            let (dropped_send, dropped_recv) = std::sync::mpsc::channel::<()>();
+            let err: Result<((), ()), _> = crate::try_join!(
+                async move {
+                    let _dropped = dropped_send;
+                    future::pending::<Result<(), &str>>().await
+                },
+                async {
+                    sleep(Duration::from_millis(10)).await;
+                    Err("failed")
+                },
+            );
+            assert_eq!(err, Err("failed"));
+            assert!(dropped_recv
+                .try_recv()
+                .is_err_and(|error| { error == std::sync::mpsc::TryRecvError::Disconnected }));
+        });
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_cancel_safe() {
+        use std::sync::Mutex;
+
+        use crate::future::{cancel_safe, critical_section};
+
+        // keeps the reports of dropped critical sections
+        struct Reports(Mutex<Vec<String>>);
+
+        impl log::Log for Reports {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                let message = record.args().to_string();
+                if message.contains("critical section") {
+                    self.0.lock().unwrap().push(message);
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        static REPORTS: Reports = Reports(Mutex::new(Vec::new()));
+        log::set_logger(&REPORTS).unwrap();
+        log::set_max_level(log::LevelFilter::Error);
+
+        let runtime = new_runtime(1, 1);
+
+        let mut write = Box::pin(cancel_safe(async {
+            let _header = critical_section("write header");
+            sleep(Duration::from_millis(1)).await;
+            let _body = critical_section("write body");
+            future::pending::<()>().await;
+        }));
+        assert!(runtime
+            .block_on_for(write.as_mut(), Duration::from_millis(50))
+            .is_pending());
+        assert_eq!(write.critical_sections(), ["write header", "write body"]);
+        drop(write);
+        assert_eq!(
+            *REPORTS.0.lock().unwrap(),
+            [r#"future dropped in the middle of critical sections ["write header", "write body"]"#]
+        );
+
+        // sections that were closed before the drop don't count
+        let completed = runtime.block_on(async {
+            timeout(
+                Duration::from_millis(10),
+                cancel_safe(async {
+                    drop(critical_section("flush"));
+                    future::pending::<()>().await;
+                }),
+            )
+            .await
+        });
+        assert!(completed.is_err());
+        assert_eq!(REPORTS.0.lock().unwrap().len(), 1);
+
+        // no cancel_safe, nothing to report
+        drop(critical_section("outside"));
+    }
+
+    #[test]
+    fn test_join_all_buffered() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        use crate::future::join_all_buffered;
+
+        let runtime = new_runtime(1, 1);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let outputs = runtime.block_on({
+            let (running, peak) = (running.clone(), peak.clone());
+            // the later ones finish first
+            join_all_buffered(
+                (0..6u64).map(move |n| {
+                    let (running, peak) = (running.clone(), peak.clone());
+                    async move {
+                        peak.fetch_max(
+                            running.fetch_add(1, Ordering::SeqCst) + 1,
+                            Ordering::SeqCst,
+                        );
+                        sleep(Duration::from_millis(12 - 2 * n)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        n * 10
+                    }
+                }),
+                2,
+            )
+        });
+        assert_eq!(outputs, [0, 10, 20, 30, 40, 50]);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+        let empty = block_on(join_all_buffered(Vec::<future::Ready<()>>::new(), 1));
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_option_future() {
+        use crate::future::OptionFuture;
+
+        let runtime = new_runtime(1, 1);
+
+        let (some, none) = runtime.block_on(async {
+            let some: OptionFuture<_> = Some(async {
+                sleep(Duration::from_millis(1)).await;
+                1
+            })
+            .into();
+            let none: OptionFuture<future::Ready<i32>> = None.into();
+            (some.await, none.await)
+        });
+        assert_eq!(some, Some(1));
+        assert_eq!(none, None);
+
+        let mut done = OptionFuture::from(Some(future::ready(1)));
+        assert_eq!(block_on(&mut done), Some(1));
+        assert!(done.is_none());
+        assert_eq!(block_on(&mut done), None);
+
+        // a select! branch without a future is skipped
+        let chosen = block_on(async {
+            let disabled: OptionFuture<future::Ready<i32>> = None.into();
+            let enabled = OptionFuture::from(Some(future::ready(2)));
+            crate::select! {
+                biased;
+                value = disabled => value,
+                value = enabled => value,
+            }
+        });
+        assert_eq!(chosen, Some(2));
+    }
+
+    #[test]
+    fn test_select_all_cancel() {
+        use crate::future::select_all_cancel;
+
+        let runtime = new_runtime(1, 1);
+
+        runtime.block_on(async {
+            let (dropped_send, dropped_recv) = std::sync::mpsc::channel::<()>();
+            let futures: Vec<_> = (0..3u64)
+                .map(|i| {
+                    let dropped = dropped_send.clone();
+                    async move {
+                        let _dropped = dropped;
+                        sleep(Duration::from_millis(10 * (3 - i))).await;
+                        i
+                    }
+                })
+                .collect();
+            drop(dropped_send);
+
+            assert_eq!(select_all_cancel(futures).await, (2, 2));
+            // the losers were dropped along with their senders
+            assert_eq!(
+                dropped_recv.try_recv(),
+                Err(std::sync::mpsc::TryRecvError::Disconnected)
+            );
+        });
+    }
+
+    #[test]
+    fn test_idle_worker_parks() {
+        let runtime = new_runtime(1, 1);
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        let metrics = runtime.metrics();
+        assert_eq!(metrics.parked_workers, 1);
+        assert!(metrics.failed_steals > 0);
+
+        // a parked worker still picks up new tasks
+        assert_eq!(runtime.block_on(async { 1 }), 1);
+    }
+
+    #[test]
+    fn test_block_on_panicked_future() {
+        let runtime = new_runtime(2, 1);
+
+        // the worker polling it dies, the caller isn't left hanging
+        let panicked = runtime.try_block_on(async {
+            sleep(Duration::from_millis(10)).await;
+            panic!("lost in flight");
+        });
+        let error = panicked.unwrap_err();
+        assert!(matches!(error, JoinError::Panic(_)));
+        assert_eq!(error.to_string(), "task panicked: lost in flight");
+
+        let panicked = runtime.spawn_blocking(|| panic!("lost in {}", "flight"));
+        assert_eq!(
+            panicked.join().unwrap_err().panic_message().as_deref(),
+            Some("task panicked: lost in flight")
+        );
+
+        // the other worker is still around
+        assert_eq!(runtime.try_block_on(async { 1 }).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_join_error_panic_message() {
+        let runtime = new_runtime(1, 1);
+
+        let panicked = runtime.spawn_blocking(|| std::panic::panic_any(42));
+        let error = panicked.join().unwrap_err();
+        assert_eq!(error.panic_message().as_deref(), Some("task panicked"));
+        assert_eq!(error.to_string(), "task panicked");
+
+        assert_eq!(JoinError::Cancelled.panic_message(), None);
+        assert_eq!(JoinError::Lost.panic_message(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "block_on future didn't complete: task panicked: lost in flight")]
+    fn test_block_on_panics_when_panicked() {
+        let runtime = new_runtime(1, 1);
+        runtime.block_on(async { panic!("lost in flight") });
+    }
+
+    #[test]
+    fn test_blocking_thread_counts_after_panic() {
+        use crate::threadpool::ThreadPool;
+
+        // the blocking threads pick up the handle of the current runtime
+        let _runtime = new_runtime(1, 1);
+        let pool = ThreadPool::new(1, 0);
+
+        let panicked = pool.spawn_blocking(|| panic!("lost in flight"));
+        assert!(matches!(panicked.join(), Err(JoinError::Panic(_))));
+        // the thread survives the panic and is free again
+        assert_eq!(pool.num_threads(), 1);
+        assert_eq!(pool.busy_threads(), 0);
+
+        assert_eq!(pool.spawn_blocking(|| 1).join().unwrap(), 1);
+        assert_eq!(pool.num_threads(), 1);
+    }
+
+    #[test]
+    fn test_shutdown_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let runtime = new_runtime(1, 1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        for (priority, name) in [(0, "close"), (10, "flush"), (0, "deregister"), (5, "panic")] {
+            let order = order.clone();
+            runtime.on_shutdown_hook(priority, move || async move {
+                order.lock().unwrap().push(name);
+                if name == "panic" {
+                    panic!("shutdown hook failed");
+                }
+            });
+        }
+        // panics before it even returns its future
+        runtime.on_shutdown_hook(1, || -> std::future::Ready<()> {
+            panic!("shutdown hook failed");
+        });
+
+        assert_eq!(runtime.shutdown_gracefully(), 2);
+        assert_eq!(
+            *order.lock().unwrap(),
+            ["flush", "panic", "close", "deregister"]
+        );
+        assert!(runtime.is_draining());
+
+        // the worker survived the panic and the hooks don't run twice
+        assert_eq!(runtime.block_on(async { 1 }), 1);
+        assert_eq!(runtime.shutdown_gracefully(), 0);
+        assert_eq!(order.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_shutdown_during_block_on() {
+        use std::{sync::mpsc, thread};
+
+        let runtime = new_runtime(2, 1);
+        // completed before the shutdown
+        assert_eq!(runtime.try_block_on(async { 1 }).unwrap(), 1);
+
+        let (started_send, started_recv) = mpsc::channel();
+        let blocked = thread::spawn({
+            let runtime = runtime.clone();
+            move || {
+                let started = Instant::now();
+                let output = runtime.try_block_on(async move {
+                    started_send.send(()).unwrap();
+                    sleep(Duration::from_secs(10)).await;
+                });
+                (output, started.elapsed())
+            }
+        });
+
+        started_recv.recv().unwrap();
+        runtime.shutdown();
+        let (output, elapsed) = blocked.join().unwrap();
+        assert!(matches!(output, Err(JoinError::Shutdown)));
+        assert!(elapsed < Duration::from_secs(10));
+
+        // nothing runs anymore
+        assert!(matches!(
+            runtime.try_block_on(async { 1 }),
+            Err(JoinError::Shutdown)
+        ));
+        assert!(matches!(
+            runtime.spawn(async { 1 }).join(),
+            Err(JoinError::Draining)
+        ));
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while runtime.metrics().live_workers > 0 && Instant::now() < deadline {
+            thread::yield_now();
+        }
+        assert_eq!(runtime.metrics().live_workers, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "block_on future didn't complete: runtime was shut down")]
+    fn test_block_on_panics_after_shutdown() {
+        let runtime = new_runtime(1, 1);
+        runtime.shutdown();
+        runtime.block_on(async { 1 });
+    }
+
+    #[test]
+    fn test_spawn_eager() {
+        use std::thread;
+
+        let runtime = new_runtime(1, 1);
+        let caller = thread::current().id();
+
+        // ready right away, the caller runs it
+        let ready = runtime.spawn_eager(async { thread::current().id() });
+        assert_eq!(ready.join().unwrap(), caller);
+
+        // the task's own context is set during the inline poll, and its
+        // waker hands it over to the workers
+        let pending = runtime.spawn_eager(async {
+            let has_context = crate::task::try_extensions(|_| ()).is_some();
+            let polled_on = thread::current().id();
+            sleep(Duration::from_millis(5)).await;
+            (has_context, polled_on, thread::current().id())
+        });
+        let (has_context, first_poll, second_poll) = pending.join().unwrap();
+        assert!(has_context);
+        assert_eq!(first_poll, caller);
+        assert_ne!(second_poll, caller);
+
+        // from within a task, the spawner's context is back after the poll
+        let spawner_context = runtime.block_on({
+            let runtime = runtime.clone();
+            async move {
+                crate::task::extensions(|extensions| extensions.insert("spawner"));
+                let child = runtime.spawn_eager(async {
+                    crate::task::extensions(|extensions| extensions.get::<&str>().copied())
+                });
+                let spawner =
+                    crate::task::extensions(|extensions| extensions.get::<&str>().copied());
+                (child.await.unwrap(), spawner)
+            }
+        });
+        assert_eq!(spawner_context, (None, Some("spawner")));
+    }
+
+    #[test]
+    fn test_spawn_eager_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let runtime = new_runtime(1, 1);
+
+        let spawned = catch_unwind(AssertUnwindSafe(|| {
+            runtime.spawn_eager(async { panic!("panicked on the spawner") })
+        }));
+        assert!(spawned.is_err());
+        // the panicking task isn't left as the current one
+        assert!(crate::task::try_extensions(|_| ()).is_none());
+        #[cfg(feature = "task-registry")]
+        assert!(runtime.tasks().is_empty());
+        #[cfg(feature = "memory-accounting")]
+        assert_eq!(runtime.metrics().live_task_bytes, 0);
+
+        // from within a task, the spawner's context is back after the panic
+        let spawner_context = runtime.block_on({
+            let runtime = runtime.clone();
+            async move {
+                crate::task::extensions(|extensions| extensions.insert("spawner"));
+                let spawned = catch_unwind(AssertUnwindSafe(|| {
+                    runtime.spawn_eager(async { panic!("panicked on the spawner") })
+                }));
+                assert!(spawned.is_err());
+                crate::task::extensions(|extensions| extensions.get::<&str>().copied())
+            }
+        });
+        assert_eq!(spawner_context, Some("spawner"));
+        #[cfg(feature = "task-registry")]
+        assert!(runtime.tasks().is_empty());
+    }
+
+    #[test]
+    fn test_spawn_with_notify() {
+        struct NotifyWaker(crossbeam_channel::Sender<()>);
+
+        impl futures::task::ArcWake for NotifyWaker {
+            fn wake_by_ref(arc_self: &std::sync::Arc<Self>) {
+                arc_self.0.send(()).unwrap();
+            }
+        }
+
+        struct PanickingWaker;
+
+        impl futures::task::ArcWake for PanickingWaker {
+            fn wake_by_ref(_: &std::sync::Arc<Self>) {
+                panic!("oops");
+            }
+        }
+
+        let runtime = new_runtime(1, 1);
+
+        let (send, recv) = crossbeam_channel::bounded(1);
+        let waker = futures::task::waker(std::sync::Arc::new(NotifyWaker(send)));
+        let handle = runtime.spawn_with_notify(async { 1 }, waker);
+
+        recv.recv().unwrap();
+        assert_eq!(handle.join().unwrap(), 1);
+
+        let waker = futures::task::waker(std::sync::Arc::new(PanickingWaker));
+        let handle = runtime.spawn_with_notify(async { 2 }, waker);
+        assert_eq!(handle.join().unwrap(), 2);
+
+        // the worker survived the panicking waker
+        assert_eq!(runtime.block_on(async { 3 }), 3);
+    }
+
+    #[test]
+    fn test_spawn_wake_ratio() {
+        let runtime = Builder::new()
+            .worker_threads(1)
+            .max_blocking_threads(1)
+            .spawn_wake_ratio(1, 2)
+            .build();
+
+        // yields a few times so that the task goes through the wake queue
+        let yield_times = |mut n: usize| {
+            future::poll_fn(move |cx| {
+                if n == 0 {
+                    return std::task::Poll::Ready(());
+                }
+                n -= 1;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            })
+        };
+
+        let handles: Vec<_> = (0..10)
+            .map(|i| runtime.spawn(async move { yield_times(i).await }))
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_heartbeat_reports_stuck_task() {
+        let (send, recv) = crossbeam_channel::unbounded();
+        let runtime = Builder::new()
+            .worker_threads(2)
+            .max_blocking_threads(1)
+            .heartbeat(Duration::from_millis(20), move |heartbeats| {
+                let _ = send.send(heartbeats.to_vec());
+            })
+            .build();
+
+        let idle = runtime.spawn(future::pending::<()>());
+        // blocks the worker inside the poll
+        let stuck = runtime.spawn(async { std::thread::sleep(Duration::from_millis(200)) });
+
+        let heartbeats = loop {
+            let heartbeats = recv.recv().unwrap();
+            if heartbeats.iter().any(|heartbeat| heartbeat.suspicious) {
+                break heartbeats;
+            }
+        };
+
+        let find = |id| heartbeats.iter().find(|heartbeat| Some(heartbeat.id) == id);
+
+        let stuck_heartbeat = find(stuck.id()).unwrap();
+        assert_eq!(stuck_heartbeat.state, TaskState::Running);
+        assert!(stuck_heartbeat.suspicious);
+
+        let idle_heartbeat = find(idle.id()).unwrap();
+        assert_eq!(idle_heartbeat.state, TaskState::Idle);
+        assert!(!idle_heartbeat.suspicious);
+
+        stuck.join().unwrap();
+        idle.abort();
+    }
+
+    #[test]
+    fn test_duplex_backpressure() {
+        let (mut one, mut two) = duplex(4);
+
+        block_on(async {
+            assert_eq!(one.write(b"hello world").await.unwrap(), 4);
+            // the pipe is full until the other side reads
+            assert!(one.write(b"o").now_or_never().is_none());
+
+            let mut buf = [0; 8];
+            assert_eq!(two.read(&mut buf).await.unwrap(), 4);
+            assert_eq!(&buf[..4], b"hell");
+
+            assert_eq!(one.write(b"o").await.unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn test_duplex_half_close() {
+        let (mut one, mut two) = duplex(16);
+
+        block_on(async {
+            one.write_all(b"ping").await.unwrap();
+            crate::io::AsyncWriteExt::close(&mut one).await.unwrap();
+
+            let mut received = Vec::new();
+            two.read_to_end(&mut received).await.unwrap();
+            assert_eq!(received, b"ping");
+
+            // the other direction is still open
+            two.write_all(b"pong").await.unwrap();
+            let mut buf = [0; 4];
+            one.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"pong");
+
+            drop(one);
+            let error = two.write(b"lost").await.unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+        });
+    }
+
+    #[cfg(feature = "wake-sources")]
+    #[test]
+    fn test_wake_sources() {
+        let runtime = new_runtime(1, 1);
+
+        let (done_send, done_recv) = futures::channel::oneshot::channel::<()>();
+        let (waiting_send, waiting_recv) = std::sync::mpsc::channel();
+        let handle = runtime.spawn(async move {
+            for _ in 0..3 {
+                sleep(Duration::from_millis(5)).await;
+            }
+            crate::runtime::current()
+                .spawn_blocking(|| std::thread::sleep(Duration::from_millis(5)))
+                .await
+                .unwrap();
+            waiting_send.send(()).unwrap();
+            // a channel that doesn't label its wakes
+            done_recv.await.unwrap();
+        });
+        let id = handle.id().unwrap();
+
+        waiting_recv.recv().unwrap();
+        let stats = runtime.task_stats(id).unwrap();
+        assert_eq!(stats.wakes.timer, 3);
+        assert_eq!(stats.wakes.channel, 1);
+        assert_eq!(stats.wakes.manual, 0);
+
+        done_send.send(()).unwrap();
+        handle.join().unwrap();
+        assert!(runtime.task_stats(id).is_none());
+    }
+
+    #[cfg(feature = "await-locations")]
+    #[test]
+    fn test_await_locations() {
+        let runtime = new_runtime(1, 1);
+
+        let (line_send, line_recv) = std::sync::mpsc::channel();
+        let handle = runtime.spawn(async move {
+            let (delay, line) = (sleep(Duration::from_secs(10)), line!());
+            line_send.send(line).unwrap();
+            delay.await;
+        });
+        let id = handle.id().unwrap();
+        let line = line_recv.recv().unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let point = loop {
+            if let Some(point) = runtime.task_stats(id).unwrap().last_await {
+                break point;
+            }
+            assert!(Instant::now() < deadline, "never suspended");
+            std::thread::sleep(Duration::from_millis(5));
+        };
+        assert_eq!(point.what, "sleep");
+        assert_eq!(point.location.file(), file!());
+        assert_eq!(point.location.line(), line);
+
+        handle.abort();
+    }
+
+    #[cfg(feature = "task-registry")]
+    #[test]
+    fn test_task_registry() {
+        let runtime = new_runtime(1, 1);
+
+        let handle = runtime.spawn_named("forever", future::pending::<()>());
+        let id = handle.id().unwrap();
+
+        let info = loop {
+            let tasks = runtime.tasks();
+            let info = tasks.into_iter().find(|info| info.id == id).unwrap();
+            // wait until the worker polled it and it went back to waiting
+            if info.polls > 0 && info.state == TaskState::Idle {
+                break info;
+            }
+        };
+        assert_eq!(info.name.as_deref(), Some("forever"));
+
+        handle.abort();
+        handle.join().unwrap_err();
+
+        assert!(runtime.tasks().iter().all(|info| info.id != id));
+    }
+
+    #[test]
+    fn test_drain_worker() {
+        let runtime = new_runtime(1, 1);
+
+        runtime.drain_worker(0);
+        while !runtime.is_worker_drained(0) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let handle = runtime.spawn(async move { sender.send(()).unwrap() });
+
+        // the only worker is drained so nobody picks the task up
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+
+        runtime.resume_worker(0);
+        handle.join().unwrap();
+        assert!(!runtime.is_worker_drained(0));
+    }
+
+    #[test]
+    fn test_sleep_coalescing() {
+        let runtime = Builder::new()
+            .worker_threads(2)
+            .timer_granularity(Duration::from_millis(20))
+            .build();
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(5);
+
+        // deadlines a few microseconds apart share a tick, they all fire
+        // at the end of it
+        let handles: Vec<_> = (0..16)
+            .map(|i| {
+                let deadline = deadline + Duration::from_micros(i);
+                runtime.spawn(async move {
+                    sleep_until(deadline).await;
+                    Instant::now()
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            let woken = handle.join().unwrap();
+            assert!(woken >= deadline);
+            assert!(woken - start < Duration::from_secs(1));
+        }
+
+        let elapsed = runtime.block_on(async {
+            let start = Instant::now();
+            sleep(Duration::from_millis(30)).await;
+            start.elapsed()
+        });
+        assert!(elapsed >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_ordered_join_set() {
+        let runtime = new_runtime(4, 1);
+
+        let results = runtime.block_on(async {
+            let mut set = OrderedJoinSet::new(3);
+
+            // the later tasks finish first
+            for i in 0..3u64 {
+                set.spawn(async move {
+                    sleep(Duration::from_millis(30 - i * 10)).await;
+                    i
+                })
+                .unwrap_or_else(|_| panic!("set is not full yet"));
+            }
+            assert!(set.is_full());
+            assert!(set.spawn(async { 3 }).is_err());
+
+            let mut results = Vec::new();
+            while let Some(result) = set.join_next().await {
+                results.push(result.unwrap());
+            }
+            results
+        });
+
+        assert_eq!(results, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_worker_cpu_time() {
+        let runtime = new_runtime(1, 1);
+
+        // blocking the worker takes wall-clock time but hardly any CPU
+        runtime.block_on(async { std::thread::sleep(Duration::from_millis(50)) });
+
+        let metrics = runtime.metrics();
+        let worker = &metrics.workers[0];
+        assert!(worker.busy_time >= Duration::from_millis(50));
+        if cfg!(target_os = "linux") {
+            assert!(worker.cpu_time.unwrap() + Duration::from_millis(40) < worker.busy_time);
+        } else {
+            assert!(worker.cpu_time.is_none());
+        }
+    }
+
+    #[test]
+    fn test_task_guard() {
+        let runtime = new_runtime(1, 1);
+
+        // see whether the worker dropped the future
+        struct Dropped(crossbeam_channel::Sender<()>);
+        impl Drop for Dropped {
+            fn drop(&mut self) {
+                self.0.send(()).unwrap();
+            }
+        }
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let dropped = Dropped(sender.clone());
+        let guard = runtime.spawn_guarded(async move {
+            let _dropped = dropped;
+            future::pending::<()>().await
+        });
+        drop(guard);
+        receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        let dropped = Dropped(sender);
+        let handle = runtime
+            .spawn_guarded(async move {
+                let _dropped = dropped;
+                sleep(Duration::from_millis(10)).await;
+                1
+            })
+            .release();
+        // a released task isn't aborted
+        assert_eq!(handle.join().unwrap(), 1);
+        receiver.try_recv().unwrap();
+
+        let guard = runtime.spawn_guarded(async { 2 });
+        assert_eq!(runtime.block_on(guard).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_collect() {
+        use std::collections::HashMap;
+
+        let items: Vec<_> = block_on(StreamExt::collect(stream::iter(1..=3)));
+        assert_eq!(items, vec![1, 2, 3]);
+
+        let map: HashMap<_, _> = block_on(StreamExt::collect(stream::iter([("a", 1), ("b", 2)])));
+        assert_eq!(map, HashMap::from([("a", 1), ("b", 2)]));
+
+        let text: String = block_on(StreamExt::collect(stream::iter("abc".chars())));
+        assert_eq!(text, "abc");
+
+        let all: Result<Vec<_>, &str> = block_on(StreamExt::collect(stream::iter([Ok(1), Ok(2)])));
+        assert_eq!(all, Ok(vec![1, 2]));
+
+        // the stream isn't polled past the first error
+        let mut polled = 0;
+        let results = stream::iter(
+            [Ok(1), Err("first"), Err("second"), Ok(4)]
+                .into_iter()
+                .inspect(|_| polled += 1),
+        );
+        let first_error: Result<Vec<i32>, &str> = block_on(StreamExt::collect(results));
+        assert_eq!(first_error, Err("first"));
+        assert_eq!(polled, 2);
+    }
+
+    #[test]
+    fn test_count_and_last() {
+        assert_eq!(block_on(StreamExt::count(stream::iter(1..=3))), 3);
+        assert_eq!(block_on(StreamExt::count(stream::empty::<i32>())), 0);
+
+        assert_eq!(block_on(StreamExt::last(stream::iter(1..=3))), Some(3));
+        assert_eq!(block_on(StreamExt::last(stream::empty::<i32>())), None);
+
+        // pending items along the way are waited for
+        let runtime = new_runtime(1, 1);
+        let last = runtime.block_on(StreamExt::last(futures::StreamExt::then(
+            stream::iter(1..=3),
+            |n| async move {
+                sleep(Duration::from_millis(1)).await;
+                n * 10
+            },
+        )));
+        assert_eq!(last, Some(30));
+    }
+
+    #[test]
+    fn test_for_each_concurrent() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        };
+
+        let runtime = new_runtime(1, 1);
+
+        let run = |limit: Option<usize>| {
+            let running = Arc::new(AtomicUsize::new(0));
+            let peak = Arc::new(AtomicUsize::new(0));
+            let done = Arc::new(Mutex::new(Vec::new()));
+
+            runtime.block_on({
+                let (peak, done) = (peak.clone(), done.clone());
+                StreamExt::for_each_concurrent(stream::iter(1..=6), limit, move |n| {
+                    let (running, peak, done) = (running.clone(), peak.clone(), done.clone());
+                    async move {
+                        peak.fetch_max(
+                            running.fetch_add(1, Ordering::SeqCst) + 1,
+                            Ordering::SeqCst,
+                        );
+                        sleep(Duration::from_millis(10)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        done.lock().unwrap().push(n);
+                    }
+                })
+            });
+
+            let mut done = done.lock().unwrap().clone();
+            done.sort();
+            assert_eq!(done, [1, 2, 3, 4, 5, 6]);
+            peak.load(Ordering::SeqCst)
+        };
+
+        assert_eq!(run(Some(2)), 2);
+        assert_eq!(run(None), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "limit must be greater than zero")]
+    fn test_for_each_concurrent_zero_limit() {
+        drop(StreamExt::for_each_concurrent(
+            stream::iter(0..3),
+            0,
+            |_| future::ready(()),
+        ));
+    }
+
+    #[test]
+    fn test_merge() {
+        // both always ready, so they take turns
+        let merged: Vec<_> =
+            block_on_stream(stream::iter([1, 3, 5]).merge(stream::iter([2, 4, 6, 8, 10])))
+                .collect();
+
+        // the second one keeps going after the first one ended
+        assert_eq!(merged, vec![1, 2, 3, 4, 5, 6, 8, 10]);
+    }
+
+    #[test]
+    fn test_zip() {
+        use std::rc::Rc;
+
+        // ends with the shorter one
+        let zipped: Vec<_> = block_on_stream(StreamExt::zip(
+            stream::iter([1, 2, 3]),
+            stream::iter(["a", "b"]),
+        ))
+        .collect();
+        assert_eq!(zipped, vec![(1, "a"), (2, "b")]);
+
+        // the left item that was buffered when the right stream ended is
+        // dropped as the stream ends, not when the `Zip` is
+        let leftover = Rc::new(());
+        let mut zipped = block_on_stream(StreamExt::zip(
+            stream::iter([leftover.clone()]),
+            stream::empty::<()>(),
+        ));
+        assert!(zipped.next().is_none());
+        assert_eq!(Rc::strong_count(&leftover), 1);
+        assert!(zipped.next().is_none());
+    }
+
+    #[test]
+    fn test_drain_mode() {
+        let runtime = new_runtime(1, 1);
+
+        let (resume_send, resume_recv) = futures::channel::oneshot::channel::<()>();
+        let running = runtime.spawn(async move {
+            resume_recv.await.unwrap();
+            1
+        });
+
+        runtime.drain_mode(true);
+        assert!(runtime.is_draining());
+        assert!(matches!(
+            runtime.try_spawn(async {}),
+            Err(SpawnError::Draining)
+        ));
+        assert!(matches!(
+            runtime.spawn(async { unreachable!() }).join(),
+            Err(JoinError::Draining)
+        ));
+        let rejected = runtime.block_on(async {
+            let mut set = JoinSet::<u32>::new();
+            let abort_handle = set.spawn(async { unreachable!() });
+            // nothing to abort, the task is already done
+            abort_handle.abort();
+            set.join_next().await
+        });
+        assert!(matches!(rejected, Some(Err(JoinError::Draining))));
+        // the running task is still woken up and finishes
+        resume_send.send(()).unwrap();
+        assert_eq!(running.join().unwrap(), 1);
+        assert_eq!(runtime.block_on(async { 2 }), 2);
+
+        runtime.drain_mode(false);
+        assert_eq!(runtime.spawn(async { 3 }).join().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_spawn_rate_limit() {
+        let runtime = Builder::new()
+            .worker_threads(1)
+            .spawn_rate_limit(10)
+            .build();
+
+        // the bucket starts with a second worth of tokens
+        for _ in 0..10 {
+            runtime.try_spawn(async {}).unwrap();
+        }
+        assert!(matches!(
+            runtime.try_spawn(async {}),
+            Err(SpawnError::RateLimited)
+        ));
+
+        let start = Instant::now();
+        let result = runtime.block_on({
+            let runtime = runtime.clone();
+            async move { runtime.spawn_throttled(async { 1 }).await.await }
+        });
+        assert_eq!(result.unwrap(), 1);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // the block_on task counts too
+        assert_eq!(runtime.metrics().spawned_tasks, 12);
+    }
+
+    #[test]
+    fn test_framed_lines() {
+        // a tiny pipe so that lines span several reads
+        let (mut writer, reader) = duplex(3);
+
+        let write = async move {
+            writer.write_all(b"hello\r\nwor").await.unwrap();
+            writer.write_all(b"ld\n\nlast").await.unwrap();
+        };
+        let read = async move {
+            let mut lines = Vec::new();
+            let mut framed = Framed::new(reader, LinesCodec::new());
+            while let Some(line) = futures::StreamExt::next(&mut framed).await {
+                lines.push(line.unwrap());
+            }
+            lines
+        };
+
+        let ((), lines) = block_on(future::join(write, read));
+        assert_eq!(lines, vec!["hello", "world", "", "last"]);
+    }
+
+    #[test]
+    fn test_framed_length_delimited() {
+        use futures::SinkExt;
+
+        let (one, two) = duplex(5);
+        let mut sender = Framed::new(one, LengthDelimitedCodec::new());
+        let receiver = Framed::new(two, LengthDelimitedCodec::with_max_frame_length(16));
+
+        let send = async move {
+            sender.send(b"first frame".to_vec()).await.unwrap();
+            sender.send(Vec::new()).await.unwrap();
+            // the receiver hangs up on this one halfway
+            let _ = sender.send(b"too long for the receiver".to_vec()).await;
+        };
+        let receive = std::thread::spawn(move || block_on_stream(receiver).collect::<Vec<_>>());
+
+        block_on(send);
+        let frames = receive.join().unwrap();
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].as_ref().unwrap(), b"first frame");
+        assert!(frames[1].as_ref().unwrap().is_empty());
+        assert_eq!(
+            frames[2].as_ref().unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_join_set() {
+        let runtime = new_runtime(2, 1);
+
+        let mut results = runtime.block_on(async {
+            let mut set = JoinSet::new();
+            for i in 0..3 {
+                set.spawn(async move { i });
+            }
+            set.spawn(future::pending()).abort();
+
+            let mut results = Vec::new();
+            while let Some(result) = set.join_next().await {
+                results.push(result.ok());
+            }
+            results
+        });
+
+        results.sort();
+        assert_eq!(results, vec![None, Some(0), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn test_join_set_panicked_task() {
+        // the worker polling the panicking task dies, the other one goes on
+        let runtime = new_runtime(2, 1);
+
+        let results = runtime.block_on(async {
+            let mut set = JoinSet::new();
+            set.spawn(async { panic!("lost in flight") });
+            set.spawn(async { 1 });
+            let mut results = Vec::new();
+            while let Some(result) = set.join_next().await {
+                results.push(result);
+            }
+            results
+        });
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().any(|result| matches!(result, Ok(1))));
+        assert!(results
+            .iter()
+            .any(|result| matches!(result, Err(JoinError::Lost))));
+    }
+
+    #[test]
+    fn test_join_set_batching() {
+        let runtime = new_runtime(2, 1);
+
+        runtime.block_on(async {
+            let mut set = JoinSet::with_batching(4, Duration::from_millis(50));
+
+            for i in 0..4 {
+                set.spawn(async move { i });
+            }
+            // a late task so the batch isn't complete just because nothing
+            // else is running
+            set.spawn(sleep(Duration::from_millis(200)).map(|()| 4));
+
+            let start = Instant::now();
+            assert_eq!(set.join_next_batch().await.len(), 4);
+            assert!(start.elapsed() < Duration::from_millis(200));
+
+            set.spawn(async { 5 });
+            // a partial batch is flushed on timeout
+            let start = Instant::now();
+            assert_eq!(set.join_next().await.unwrap().unwrap(), 5);
+            assert!(start.elapsed() >= Duration::from_millis(40));
+
+            assert_eq!(set.join_next().await.unwrap().unwrap(), 4);
+            assert!(set.join_next_batch().await.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_block_on_for() {
+        let runtime = new_runtime(1, 1);
+
+        let mut future = std::pin::pin!(async {
+            sleep(Duration::from_millis(50)).await;
+            sleep(Duration::from_millis(50)).await;
+            1
+        });
+
+        let mut frames = 0;
+        let result = loop {
+            match runtime.block_on_for(future.as_mut(), Duration::from_millis(10)) {
+                std::task::Poll::Ready(result) => break result,
+                std::task::Poll::Pending => frames += 1,
+            }
+        };
+
+        assert_eq!(result, 1);
+        // the sleeps carried over between calls, or this would never end
+        assert!(frames >= 5);
+    }
+
+    #[test]
+    fn test_worker_local() {
+        use std::cell::Cell;
+
+        worker_local! {
+            static POLLS: Cell<u32> = Cell::new(0);
+        }
+
+        let runtime = new_runtime(1, 1);
+
+        let polls = runtime.block_on(async {
+            POLLS.with(|polls| polls.set(polls.get() + 1));
+            POLLS.with(Cell::get)
+        });
+        assert_eq!(polls, 1);
+
+        // the single worker kept its instance
+        let polls = runtime.block_on(async { POLLS.with(Cell::get) });
+        assert_eq!(polls, 1);
+
+        assert!(POLLS.try_with(Cell::get).is_none());
+    }
+
+    #[test]
+    fn test_throttle() {
+        let runtime = new_runtime(1, 1);
+
+        let elapsed = runtime.block_on(async {
+            let start = Instant::now();
+            let items = stream::iter(0..3)
+                .throttle(Duration::from_millis(20))
+                .fold(Vec::new(), |mut items, item| {
+                    items.push(item);
+                    items
+                })
+                .await;
+            assert_eq!(items, vec![0, 1, 2]);
+            start.elapsed()
+        });
+
+        // two waits between the three items
+        assert!(elapsed >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_debounce() {
+        let runtime = new_runtime(1, 1);
+
+        let items = runtime.block_on(async {
+            // two bursts with a pause in between
+            let pause = stream::once(sleep(Duration::from_millis(100)).map(|()| 3));
+            let bursts = futures::StreamExt::chain(
+                futures::StreamExt::chain(stream::iter([0, 1, 2]), pause),
+                stream::iter([4, 5]),
+            );
+
+            bursts
+                .debounce(Duration::from_millis(50))
+                .fold(Vec::new(), |mut items, item| {
+                    items.push(item);
+                    items
+                })
+                .await
+        });
+
+        // the last burst is flushed when the stream ends
+        assert_eq!(items, vec![2, 5]);
+    }
+
+    #[cfg(feature = "task-registry")]
+    #[test]
+    fn test_spawn_near() {
+        let runtime = new_runtime(4, 1);
+
+        // waits for the task to be polled and returns the worker it ran on
+        let worker_of = |id| loop {
+            let tasks = runtime.tasks();
+            let info = tasks.iter().find(|info| info.id == id).unwrap();
+            if let Some(worker) = info.last_worker {
+                break worker;
+            }
+        };
+
+        let producer = runtime.spawn(future::pending::<()>());
+        let worker = worker_of(producer.id().unwrap());
+
+        // with four workers, landing on the same one by chance every time
+        // is unlikely
+        for _ in 0..5 {
+            let consumer = runtime.spawn_near(producer.id().unwrap(), future::pending::<()>());
+            assert_eq!(worker_of(consumer.id().unwrap()), worker);
+            consumer.abort();
+        }
+
+        producer.abort();
+    }
+
+    #[cfg(feature = "task-registry")]
+    #[test]
+    fn test_snapshot() {
+        let runtime = new_runtime(1, 1);
+
+        let started = Instant::now();
+        let sleeper = runtime.spawn(sleep(Duration::from_secs(10)));
+        let sleeper_id = sleeper.id().unwrap();
+        while !runtime.snapshot().idle.contains(&sleeper_id) {
+            std::thread::yield_now();
+        }
+
+        // hog the only worker while the others queue up behind it
+        let (release_send, release_recv) = std::sync::mpsc::channel::<()>();
+        let (busy_send, busy_recv) = std::sync::mpsc::channel();
+        let hog = runtime.spawn(async move {
+            busy_send.send(()).unwrap();
+            release_recv.recv().unwrap();
+        });
+        busy_recv.recv().unwrap();
+        let first = runtime.spawn(async {});
+        std::thread::sleep(Duration::from_millis(2));
+        let second = runtime.spawn(async {});
+
+        let snapshot = runtime.snapshot();
+        assert_eq!(snapshot.queued, [first.id().unwrap(), second.id().unwrap()]);
+        assert_eq!(snapshot.idle, [sleeper_id]);
+        assert_eq!(snapshot.workers.len(), 1);
+        assert_eq!(snapshot.workers[0].running, hog.id());
+        assert_eq!(snapshot.workers[0].local_queue_depth, 0);
+        assert!(!snapshot.workers[0].drained);
+        assert_eq!(snapshot.timers.len(), 1);
+        assert!(snapshot.timers[0] >= started + Duration::from_secs(10));
+
+        release_send.send(()).unwrap();
+        first.join().unwrap();
+        second.join().unwrap();
+        sleeper.abort();
+        let _ = sleeper.join();
+
+        let snapshot = runtime.snapshot();
+        assert!(snapshot.queued.is_empty() && snapshot.idle.is_empty());
+        assert!(snapshot.timers.is_empty());
+    }
+
+    #[cfg(feature = "memory-accounting")]
+    #[test]
+    fn test_live_task_bytes() {
+        let runtime = new_runtime(1, 1);
+        assert_eq!(runtime.metrics().live_task_bytes, 0);
+
+        let large = |recv: futures::channel::oneshot::Receiver<()>| async move {
+            let buffer = [1u8; 1024];
+            let _ = recv.await;
+            buffer.len()
+        };
+
+        let (send, recv) = futures::channel::oneshot::channel();
+        let completed = runtime.spawn(large(recv));
+        assert!(runtime.metrics().live_task_bytes > 1024);
+        send.send(()).unwrap();
+        assert_eq!(completed.join().unwrap(), 1024);
+        assert_eq!(runtime.metrics().live_task_bytes, 0);
+
+        let (_send, recv) = futures::channel::oneshot::channel();
+        let aborted = runtime.spawn(large(recv));
+        assert!(runtime.metrics().live_task_bytes > 1024);
+        aborted.abort_handle().unwrap().abort();
+        assert!(matches!(aborted.join(), Err(JoinError::Cancelled)));
+        assert_eq!(runtime.metrics().live_task_bytes, 0);
+    }
+
+    #[cfg(feature = "memory-accounting")]
+    #[test]
+    fn test_max_queued_task_bytes() {
+        let runtime = Builder::new()
+            .worker_threads(1)
+            .max_blocking_threads(1)
+            .max_queued_task_bytes(4096)
+            .build();
+
+        // keep the only worker busy so that nothing gets started
+        let (release_send, release_recv) = std::sync::mpsc::channel::<()>();
+        let (busy_send, busy_recv) = std::sync::mpsc::channel();
+        runtime.spawn(async move {
+            busy_send.send(()).unwrap();
+            release_recv.recv().unwrap();
+        });
+        busy_recv.recv().unwrap();
+
+        let large = || async {
+            let buffer = [1u8; 4096];
+            sleep(Duration::from_millis(1)).await;
+            buffer.len()
+        };
+
+        let first = runtime.try_spawn(large()).unwrap();
+        assert!(runtime.metrics().queued_task_bytes > 4096);
+        assert!(matches!(
+            runtime.try_spawn(large()),
+            Err(SpawnError::QueueFull)
+        ));
+        // spawn itself isn't limited
+        let second = runtime.spawn(large());
+
+        let third = runtime.spawn({
+            let runtime = runtime.clone();
+            async move { runtime.spawn_throttled(large()).await.await.unwrap() }
+        });
+
+        release_send.send(()).unwrap();
+        assert_eq!(first.join().unwrap(), 4096);
+        assert_eq!(second.join().unwrap(), 4096);
+        assert_eq!(third.join().unwrap(), 4096);
+        assert_eq!(runtime.metrics().queued_task_bytes, 0);
+    }
+
+    #[test]
+    fn test_prometheus_metrics() {
+        // the only worker ran the block_on task, so it's surely live
+        let runtime = new_runtime(1, 1);
+        runtime.blocking_pool("disk \"a\"", 1);
+        runtime.block_on(async {
+            let _ = timeout(Duration::from_millis(100), async {}).await;
+        });
+
+        let text = runtime.metrics().to_prometheus();
+        let lines: Vec<_> = text.lines().collect();
+
+        for expected in [
+            "# TYPE async_runtime_live_workers gauge",
+            "async_runtime_live_workers 1",
+            "# TYPE async_runtime_spawned_tasks_total counter",
+            "async_runtime_spawned_tasks_total 1",
+            r#"async_runtime_blocking_pool_threads{pool="disk \"a\""} 0"#,
+            r#"async_runtime_timeouts_total{label="",outcome="completed"} 1"#,
+            r#"async_runtime_timeouts_total{label="",outcome="elapsed"} 0"#,
+        ] {
+            assert!(
+                lines.contains(&expected),
+                "missing {expected:?} in:\n{text}"
+            );
+        }
+        assert!(lines.iter().any(
+            |line| line.starts_with(r#"async_runtime_worker_busy_seconds_total{worker="0"} "#)
+        ));
+
+        // the block_on task was picked up once, the buckets are cumulative
+        for expected in [
+            "# TYPE async_runtime_scheduling_delay_seconds histogram",
+            r#"async_runtime_scheduling_delay_seconds_bucket{le="+Inf"} 1"#,
+            "async_runtime_scheduling_delay_seconds_count 1",
+        ] {
+            assert!(
+                lines.contains(&expected),
+                "missing {expected:?} in:\n{text}"
+            );
+        }
+        let buckets: Vec<(f64, u64)> = lines
+            .iter()
+            .filter_map(|line| {
+                let rest =
+                    line.strip_prefix(r#"async_runtime_scheduling_delay_seconds_bucket{le=""#)?;
+                let (le, count) = rest.split_once(r#""} "#)?;
+                Some((le.parse().unwrap(), count.parse().unwrap()))
+            })
+            .collect();
+        assert_eq!(buckets.first().map(|bucket| bucket.0), Some(0.000001));
+        assert_eq!(buckets.last().map(|bucket| bucket.0), Some(f64::INFINITY));
+        assert!(buckets
+            .windows(2)
+            .all(|pair| pair[0].0 < pair[1].0 && pair[0].1 <= pair[1].1));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("async_runtime_scheduling_delay_seconds_sum ")));
+
+        // every sample follows the HELP and TYPE of its metric
+        let mut described = None;
+        for line in lines {
+            if let Some(help) = line.strip_prefix("# HELP ") {
+                described = help.split(' ').next();
+            } else if !line.starts_with('#') {
+                // histograms sample under suffixed names
+                let name = line.split(['{', ' ']).next().map(|name| {
+                    ["_bucket", "_sum", "_count"]
+                        .iter()
+                        .find_map(|suffix| {
+                            name.strip_suffix(suffix)
+                                .filter(|base| Some(*base) == described)
+                        })
+                        .unwrap_or(name)
+                });
+                assert_eq!(name, described, "{line}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_scheduling_delay_metrics() {
+        const BUSY: Duration = Duration::from_millis(50);
+
+        let runtime = new_runtime(1, 1);
+
+        // the only worker is stuck in the first task while the second one
+        // waits in the queue
+        let busy = runtime.spawn(async { std::thread::sleep(BUSY) });
+        let queued = runtime.spawn(async {});
+        busy.join().unwrap();
+        queued.join().unwrap();
+
+        let metrics = runtime.metrics();
+        assert_eq!(metrics.scheduled_polls, 2);
+        assert!(metrics.max_scheduling_delay >= BUSY);
+        assert!(metrics.mean_scheduling_delay >= BUSY / 2);
+        assert!(metrics.mean_scheduling_delay <= metrics.max_scheduling_delay);
+
+        // the queued task lands in a bucket bounded above 50ms, the other
+        // one most likely in a much lower one
+        let counts: u64 = metrics
+            .scheduling_delays
+            .iter()
+            .map(|bucket| bucket.count)
+            .sum();
+        assert_eq!(counts, 2);
+        assert!(metrics
+            .scheduling_delays
+            .iter()
+            .filter(|bucket| bucket.upper_bound.is_none_or(|bound| bound >= BUSY))
+            .any(|bucket| bucket.count > 0));
+        let bounds: Vec<_> = metrics
+            .scheduling_delays
+            .iter()
+            .map(|bucket| bucket.upper_bound)
+            .collect();
+        assert_eq!(bounds[0], Some(Duration::from_micros(1)));
+        assert_eq!(bounds[1], Some(Duration::from_micros(2)));
+        assert_eq!(bounds.last(), Some(&None));
+        assert!(bounds.windows(2).all(|pair| match pair {
+            [Some(lower), Some(upper)] => lower < upper,
+            [Some(_), None] => true,
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_scheduling_delay_counts_from_first_wake() {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        };
+
+        const PAUSE: Duration = Duration::from_millis(50);
+
+        let runtime = new_runtime(1, 1);
+
+        let waker = Arc::new(Mutex::new(None::<std::task::Waker>));
+        let done = Arc::new(AtomicBool::new(false));
+        let (polled_send, polled_recv) = std::sync::mpsc::channel();
+        let woken = runtime.spawn({
+            let waker = waker.clone();
+            let done = done.clone();
+            future::poll_fn(move |cx| {
+                *waker.lock().unwrap() = Some(cx.waker().clone());
+                let _ = polled_send.send(());
+                if done.load(Ordering::Acquire) {
+                    std::task::Poll::Ready(())
+                } else {
+                    std::task::Poll::Pending
+                }
+            })
+        });
+        polled_recv.recv().unwrap();
+
+        // keep the only worker busy while the task is woken twice
+        let (release_send, release_recv) = std::sync::mpsc::channel::<()>();
+        let (busy_send, busy_recv) = std::sync::mpsc::channel();
+        let busy = runtime.spawn(async move {
+            busy_send.send(()).unwrap();
+            release_recv.recv().unwrap();
+        });
+        busy_recv.recv().unwrap();
+
+        done.store(true, Ordering::Release);
+        let wake = || waker.lock().unwrap().as_ref().unwrap().wake_by_ref();
+        wake();
+        std::thread::sleep(PAUSE);
+        wake();
+        std::thread::sleep(PAUSE / 5);
+        release_send.send(()).unwrap();
+        busy.join().unwrap();
+        woken.join().unwrap();
+
+        // the second wake didn't restart the clock
+        assert!(runtime.metrics().max_scheduling_delay >= PAUSE);
+    }
+
+    #[test]
+    fn test_timeout_metrics() {
+        let runtime = new_runtime(1, 1);
+
+        runtime.block_on(async {
+            let fast = timeout(Duration::from_secs(1), async { 1 });
+            assert_eq!(fast.await, Ok(1));
+
+            let slow = timeout(Duration::from_millis(10), future::pending::<()>()).label("slow");
+            assert!(slow.await.is_err());
+
+            let slow = timeout(Duration::from_millis(10), future::pending::<()>()).label("slow");
+            assert!(slow.await.is_err());
+        });
+
+        let timeouts = runtime.metrics().timeouts;
+        assert_eq!(timeouts.len(), 2);
+        assert_eq!(timeouts[0].label, None);
+        assert_eq!((timeouts[0].completed, timeouts[0].elapsed), (1, 0));
+        assert_eq!(timeouts[1].label, Some("slow"));
+        assert_eq!((timeouts[1].completed, timeouts[1].elapsed), (0, 2));
+    }
+
+    #[test]
+    fn test_suspend_resume() {
+        use std::sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        };
+
+        let runtime = new_runtime(2, 1);
+
+        let ticks = Arc::new(AtomicU32::new(0));
+        let handle = runtime.spawn({
+            let ticks = ticks.clone();
+            async move {
+                loop {
+                    sleep(Duration::from_millis(2)).await;
+                    ticks.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+        let task = handle.abort_handle().unwrap();
+
+        task.suspend();
+        // let a poll that was under way finish
+        std::thread::sleep(Duration::from_millis(20));
+        let suspended_at = ticks.load(Ordering::Relaxed);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(ticks.load(Ordering::Relaxed), suspended_at);
+
+        // the timer wake that came in meanwhile gets it going again
+        task.resume();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(ticks.load(Ordering::Relaxed) > suspended_at);
+
+        task.suspend();
+        handle.abort();
+        assert!(matches!(handle.join(), Err(JoinError::Cancelled)));
+    }
+
+    #[test]
+    fn test_io_ext() {
+        use crate::io::{AsyncReadExt, AsyncWriteExt};
+        use std::io::ErrorKind;
+
+        // spelled out since the futures traits with the same methods are in
+        // scope too
+
+        // the pipe is smaller than the message so every call loops
+        let (mut one, mut two) = duplex(3);
+
+        let write = async move {
+            AsyncWriteExt::write_all(&mut one, b"hello world")
+                .await
+                .unwrap();
+        };
+        let read = async move {
+            let mut hello = [0; 5];
+            AsyncReadExt::read_exact(&mut two, &mut hello)
+                .await
+                .unwrap();
+            assert_eq!(&hello, b"hello");
+
+            let mut rest = b"...".to_vec();
+            let n = AsyncReadExt::read_to_end(&mut two, &mut rest)
+                .await
+                .unwrap();
+            assert_eq!(n, 6);
+            assert_eq!(rest, b"... world");
+
+            let mut more = [0; 1];
+            let error = AsyncReadExt::read_exact(&mut two, &mut more)
+                .await
+                .unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        };
+
+        block_on(future::join(write, read));
+    }
+
+    #[test]
+    fn test_take_until() {
+        // the terminator is ready from the start but the first item still
+        // comes through
+        let items: Vec<_> =
+            block_on_stream(stream::iter(0..5).take_until(future::ready(()))).collect();
+        assert_eq!(items, vec![0]);
+
+        let items: Vec<_> =
+            block_on_stream(stream::iter(0..5).take_until(future::pending::<()>())).collect();
+        assert_eq!(items, vec![0, 1, 2, 3, 4]);
+
+        // ends without an item when the stream is waiting
+        let items: Vec<_> =
+            block_on_stream(stream::pending::<i32>().take_until(future::ready(()))).collect();
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn test_task_extensions() {
+        use crate::task::{self, Extensions};
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct User(&'static str);
+
+        let runtime = new_runtime(1, 1);
+
+        let (user, child_user) = runtime.block_on({
+            let runtime = runtime.clone();
+            async move {
+                task::extensions(|extensions| extensions.insert(User("alice")));
+                // still there after an await
+                sleep(Duration::from_millis(1)).await;
+                let user = task::extensions(|extensions| extensions.get::<User>().cloned());
+
+                // not inherited
+                let child_user = runtime
+                    .spawn(async {
+                        task::extensions(|extensions| extensions.get::<User>().cloned())
+                    })
+                    .await
+                    .unwrap();
+
+                (user, child_user)
+            }
+        });
+        assert_eq!(user, Some(User("alice")));
+        assert_eq!(child_user, None);
+
+        let mut extensions = Extensions::new();
+        extensions.insert(User("bob"));
+        let user = runtime
+            .spawn_with_extensions(extensions, async {
+                task::extensions(|extensions| extensions.remove::<User>())
+            })
+            .join()
+            .unwrap();
+        assert_eq!(user, Some(User("bob")));
+
+        assert!(task::try_extensions(|_| ()).is_none());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_trace_id_in_spawn_blocking() {
+        use crate::task::{self, TraceId};
+
+        let runtime = new_runtime(1, 1);
+
+        let (in_task, in_blocking) = runtime.block_on({
+            let runtime = runtime.clone();
+            async move {
+                task::set_trace_id(TraceId(7));
+                let in_blocking = runtime.spawn_blocking(task::trace_id).await.unwrap();
+                (task::trace_id(), in_blocking)
+            }
+        });
+        assert_eq!(in_task, Some(TraceId(7)));
+        assert_eq!(in_blocking, Some(TraceId(7)));
+
+        // the pool thread doesn't keep it for the next task
+        let untraced = runtime.spawn_blocking(task::trace_id).join().unwrap();
+        assert_eq!(untraced, None);
+    }
+
+    #[test]
+    fn test_sleep_reset_lazily() {
+        let runtime = new_runtime(1, 1);
+
+        runtime.block_on(async {
+            let started = Instant::now();
+            let mut delay = sleep(Duration::from_millis(10));
+            assert!(futures::poll!(&mut delay).is_pending());
+
+            // pushed back over and over, the first deadline wakes the task
+            // early and it waits again for the latest one
+            for _ in 0..1000 {
+                delay.reset(Instant::now() + Duration::from_millis(40));
+                assert!(futures::poll!(&mut delay).is_pending());
+            }
+            (&mut delay).await;
+            assert!(started.elapsed() >= Duration::from_millis(40));
+
+            // brought forward, the old deadline doesn't hold it back
+            let started = Instant::now();
+            delay.reset(started + Duration::from_secs(10));
+            assert!(futures::poll!(&mut delay).is_pending());
+            delay.reset(started + Duration::from_millis(10));
+            (&mut delay).await;
+            assert!(started.elapsed() < Duration::from_secs(5));
+        });
+    }
+
+    #[test]
+    fn test_sleep_is_elapsed() {
+        let runtime = new_runtime(1, 1);
+
+        runtime.block_on(async {
+            let started = Instant::now();
+            let mut delay = sleep(Duration::from_millis(20));
+            assert!(delay.deadline() >= started + Duration::from_millis(20));
+            assert!(!delay.is_elapsed());
+
+            (&mut delay).await;
+            assert!(delay.is_elapsed());
+
+            let deadline = Instant::now() + Duration::from_millis(20);
+            delay.reset(deadline);
+            assert_eq!(delay.deadline(), deadline);
+            assert!(!delay.is_elapsed());
+            delay.await;
+            assert!(Instant::now() >= deadline);
+        });
+    }
+
+    #[test]
+    fn test_semaphore() {
+        use crate::sync::Semaphore;
+
+        let runtime = new_runtime(2, 1);
+        let semaphore = std::sync::Arc::new(Semaphore::new(1));
+
+        let permit = semaphore.try_acquire().unwrap();
+        assert!(semaphore.try_acquire().is_none());
+
+        let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let waiters: Vec<_> = (0..3)
+            .map(|i| {
+                let semaphore = semaphore.clone();
+                let order = order.clone();
+                let handle = runtime.spawn(async move {
+                    let _permit = semaphore.acquire().await;
+                    order.lock().unwrap().push(i);
+                });
+                // queue them up in order
+                std::thread::sleep(Duration::from_millis(10));
+                handle
+            })
+            .collect();
+
+        // a cancelled waiter gives up its place
+        runtime.block_on({
+            let semaphore = semaphore.clone();
+            async move {
+                let acquire = std::pin::pin!(semaphore.acquire());
+                assert!(futures::poll!(acquire).is_pending());
+            }
+        });
+
+        drop(permit);
+        for waiter in waiters {
+            waiter.join().unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2]);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+
+    #[test]
+    fn test_spawn_blocking_limited() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        use crate::sync::Semaphore;
+
+        let runtime = new_runtime(1, 8);
+        let limiter = Arc::new(Semaphore::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        runtime.block_on({
+            let runtime = runtime.clone();
+            let (running, max_running) = (running.clone(), max_running.clone());
+            async move {
+                let mut handles = Vec::new();
+                for _ in 0..6 {
+                    let (running, max_running) = (running.clone(), max_running.clone());
+                    let handle = runtime
+                        .spawn_blocking_limited(&limiter, move || {
+                            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            max_running.fetch_max(now, Ordering::SeqCst);
+                            std::thread::sleep(Duration::from_millis(20));
+                            running.fetch_sub(1, Ordering::SeqCst);
+                        })
+                        .await;
+                    handles.push(handle);
+                }
+                for handle in handles {
+                    handle.await.unwrap();
+                }
+                assert_eq!(limiter.available_permits(), 2);
+            }
+        });
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_stream_next() {
+        let runtime = new_runtime(2, 1);
+
+        let (send, mut recv) = futures::channel::mpsc::unbounded();
+        runtime.spawn(async move {
+            // the receiver is empty when first polled, next has to be woken
+            sleep(Duration::from_millis(20)).await;
+            send.unbounded_send(Ok(1)).unwrap();
+            send.unbounded_send(Err("bad frame")).unwrap();
+        });
+
+        runtime.block_on(async move {
+            assert_eq!(StreamExt::next(&mut recv).await, Some(Ok(1)));
+            assert_eq!(StreamExt::try_next(&mut recv).await, Err("bad frame"));
+            assert_eq!(StreamExt::try_next(&mut recv).await, Ok(None::<i32>));
+        });
+
+        let mut numbers = stream::iter(1..=2);
+        block_on(async {
+            assert_eq!(StreamExt::next(&mut numbers).await, Some(1));
+            assert_eq!(StreamExt::next(&mut numbers).await, Some(2));
+            assert_eq!(StreamExt::next(&mut numbers).await, None);
+        });
+    }
+
+    #[test]
+    fn test_stream_next_in_select() {
+        use futures::future::FusedFuture;
+
+        let mut numbers = stream::iter(1..=2);
+
+        let mut next = StreamExt::next(&mut numbers);
+        assert!(!next.is_terminated());
+        assert_eq!(block_on(&mut next), Some(1));
+        assert!(next.is_terminated());
+
+        // both can be select! branches
+        let mut words = stream::iter([Ok::<_, ()>("a")]);
+        let (number, word) = block_on(async {
+            let number = crate::select! {
+                number = StreamExt::next(&mut numbers) => number,
+            };
+            let word = crate::select! {
+                word = StreamExt::try_next(&mut words) => word,
+            };
+            (number, word)
+        });
+        assert_eq!(number, Some(2));
+        assert_eq!(word, Ok(Some("a")));
+    }
+
+    #[test]
+    fn test_stream_peekable() {
+        let mut numbers = std::pin::pin!(stream::iter(1..=3).peekable());
+
+        block_on(async {
+            assert_eq!(numbers.as_mut().peek().await, Some(&1));
+            // peeking again doesn't advance
+            assert_eq!(numbers.as_mut().peek().await, Some(&1));
+            assert_eq!(futures::StreamExt::next(&mut numbers).await, Some(1));
+            assert_eq!(futures::StreamExt::next(&mut numbers).await, Some(2));
+
+            // a peek that took the item is dropped without being awaited
+            // again, the item is still buffered
+            {
+                let mut peek = numbers.as_mut().peek();
+                assert!(futures::poll!(&mut peek).is_ready());
+            }
+            assert_eq!(futures::Stream::size_hint(&numbers), (1, Some(1)));
+            assert_eq!(futures::StreamExt::next(&mut numbers).await, Some(3));
+
+            assert_eq!(numbers.as_mut().peek().await, None);
+            assert_eq!(futures::StreamExt::next(&mut numbers).await, None);
+        });
+    }
+
+    #[test]
+    fn test_stream_peek_after_end() {
+        let mut numbers = std::pin::pin!(unfused(vec![1]).peekable());
+
+        block_on(async {
+            assert_eq!(futures::StreamExt::next(&mut numbers).await, Some(1));
+            assert_eq!(numbers.as_mut().peek().await, None);
+            // neither polls the stream again
+            assert_eq!(numbers.as_mut().peek().await, None);
+            assert_eq!(futures::StreamExt::next(&mut numbers).await, None);
+            assert_eq!(futures::Stream::size_hint(&numbers), (0, Some(0)));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "worker threads must be greater than zero")]
+    fn test_zero_worker_threads() {
+        Builder::new().worker_threads(0);
+    }
+
+    #[test]
+    fn test_spawn_in_group() {
+        let runtime = Builder::new()
+            .worker_threads(4)
+            .max_blocking_threads(1)
+            .worker_group_size(2)
+            .build();
+
+        // every task of a group runs, across its wakes, on the same two
+        // workers
+        let workers = runtime.block_on({
+            let runtime = runtime.clone();
+            async move {
+                let handles: Vec<_> = (0..16)
+                    .map(|_| {
+                        runtime.spawn_in_group("shard-1", async {
+                            let mut seen = std::collections::HashSet::new();
+                            for _ in 0..4 {
+                                seen.insert(std::thread::current().id());
+                                sleep(Duration::from_millis(1)).await;
+                            }
+                            seen
+                        })
+                    })
+                    .collect();
+                let mut workers = std::collections::HashSet::new();
+                for handle in handles {
+                    let seen = handle.await.unwrap();
+                    assert_eq!(seen.len(), 1);
+                    workers.extend(seen);
+                }
+                workers
+            }
+        });
+        assert!(workers.len() <= 2);
+
+        // still runs with the whole group drained
+        for index in 0..4 {
+            runtime.drain_worker(index);
+        }
+        runtime.resume_worker(0);
+        let value = runtime
+            .spawn_in_group("shard-1", async { 1 })
+            .join()
+            .unwrap();
+        assert_eq!(value, 1);
+    }
+
+    #[test]
+    fn test_stream_flatten_after_end() {
+        let mut flattened = StreamExt::flatten(unfused(vec![stream::iter(vec![1])]));
+        let mut flat_mapped = StreamExt::flat_map(unfused(vec![1]), |n| stream::iter(vec![n]));
+
+        block_on(async {
+            assert_eq!(futures::StreamExt::next(&mut flattened).await, Some(1));
+            // neither polls the outer stream again after its end
+            for _ in 0..2 {
+                assert_eq!(futures::StreamExt::next(&mut flattened).await, None);
+            }
+            assert_eq!(futures::StreamExt::next(&mut flat_mapped).await, Some(1));
+            for _ in 0..2 {
+                assert_eq!(futures::StreamExt::next(&mut flat_mapped).await, None);
+            }
+        });
+    }
+
+    #[test]
+    fn test_stream_flatten() {
+        let pages = stream::iter(vec![
+            stream::iter(vec![1, 2]),
+            stream::iter(vec![]),
+            stream::iter(vec![3]),
+        ]);
+        let records: Vec<_> = block_on_stream(StreamExt::flatten(pages)).collect();
+        assert_eq!(records, vec![1, 2, 3]);
+
+        // errors come out where they occurred
+        let records: Vec<Result<u32, &str>> =
+            block_on_stream(StreamExt::flat_map(stream::iter(1..=2), |page| {
+                stream::iter(vec![Ok(page * 10), Err("bad record"), Ok(page * 10 + 1)])
+            }))
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                Ok(10),
+                Err("bad record"),
+                Ok(11),
+                Ok(20),
+                Err("bad record"),
+                Ok(21)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_effective_parallelism() {
+        let runtime = new_runtime(4, 1);
+
+        let wait_for = |expected: usize| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while runtime.effective_parallelism() != expected {
+                assert!(Instant::now() < deadline, "never reached {expected}");
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        };
+
+        // idle workers park
+        wait_for(0);
+
+        let (release, released) = crossbeam_channel::bounded::<()>(0);
+        let busy: Vec<_> = (0..2)
+            .map(|_| {
+                let released = released.clone();
+                runtime.spawn(async move {
+                    let _ = released.recv();
+                })
+            })
+            .collect();
+        wait_for(2);
+
+        drop(release);
+        for handle in busy {
+            handle.join().unwrap();
+        }
+        wait_for(0);
+    }
+
+    #[test]
+    fn test_auto_scale() {
+        use crate::autoscale::AutoScaleConfig;
+
+        let runtime = Builder::new()
+            .worker_threads(4)
+            .max_blocking_threads(1)
+            .auto_scale(AutoScaleConfig {
+                min_workers: 1,
+                queue_threshold: 0,
+                grow_after: Duration::from_millis(20),
+                shrink_after: Duration::from_millis(20),
+                interval: Duration::from_millis(5),
+            })
+            .build();
+
+        let wait_for = |what: &str, done: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !done() {
+                assert!(Instant::now() < deadline, "never {what}");
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        };
+
+        // every task blocks its worker, so the queue only drains as workers
+        // are added
+        let (release, released) = crossbeam_channel::bounded::<()>(0);
+        let busy: Vec<_> = (0..4)
+            .map(|_| {
+                let released = released.clone();
+                runtime.spawn(async move {
+                    let _ = released.recv();
+                })
+            })
+            .collect();
+        wait_for("scaled up", &|| runtime.effective_parallelism() == 4);
+        assert_eq!(runtime.metrics().workers_scaled_up, 3);
+
+        drop(release);
+        for handle in busy {
+            handle.join().unwrap();
+        }
+        wait_for("scaled down", &|| {
+            runtime.metrics().workers_scaled_down == 3
+        });
+        // back to the minimum, the others stay drained
+        wait_for("drained", &|| {
+            (1..4).all(|index| runtime.is_worker_drained(index))
+        });
+        assert!(!runtime.is_worker_drained(0));
+    }
+
+    #[test]
+    fn test_interval_missed_ticks() {
+        use crate::time::{interval, MissedTickBehavior};
+
+        const PERIOD: Duration = Duration::from_millis(20);
+        // how long the consumer is busy after the first tick, three and a
+        // half periods
+        const SLOW: Duration = Duration::from_millis(70);
+
+        let runtime = new_runtime(1, 1);
+
+        runtime.block_on(async {
+            let mut ticks = interval(PERIOD);
+            let start = ticks.tick().await;
+            std::thread::sleep(SLOW);
+            // the missed ticks come out back to back, then it's on schedule
+            // again
+            for i in 1..=3 {
+                let before = Instant::now();
+                assert_eq!(ticks.tick().await, start + PERIOD * i);
+                assert!(before.elapsed() < PERIOD / 2);
+            }
+            assert_eq!(ticks.tick().await, start + PERIOD * 4);
+            assert!(start.elapsed() >= PERIOD * 4);
+
+            let mut ticks = interval(PERIOD);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let start = ticks.tick().await;
+            std::thread::sleep(SLOW);
+            let late = Instant::now();
+            assert_eq!(ticks.tick().await, start + PERIOD);
+            // a period after the late tick, not on the original schedule
+            let next = ticks.tick().await;
+            assert!(next >= late + PERIOD);
+            assert!(next < late + PERIOD + PERIOD / 2);
+            assert!(Instant::now() >= next);
+            assert_eq!(ticks.tick().await, next + PERIOD);
+
+            let mut ticks = interval(PERIOD);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let start = ticks.tick().await;
+            std::thread::sleep(SLOW);
+            assert_eq!(ticks.tick().await, start + PERIOD);
+            // the ticks at 40 and 60ms are dropped
+            assert_eq!(ticks.tick().await, start + PERIOD * 4);
+            assert!(start.elapsed() >= PERIOD * 4);
+        });
+    }
+
+    #[test]
+    fn test_interval_skip_after_long_stall() {
+        use crate::time::MissedTickBehavior;
+
+        // more missed ticks than fit in a u32
+        let tick = Instant::now();
+        let now = tick + Duration::from_secs(5000);
+        let period = Duration::from_nanos(3);
+
+        let next = MissedTickBehavior::Skip.next_tick(tick, now, period);
+        assert!(next > now);
+        assert!(next <= now + period);
+        assert_eq!((next - tick).as_nanos() % period.as_nanos(), 0);
+    }
+
+    #[test]
+    fn test_erased_output_type_mismatch() {
+        use crate::threadpool::ErasedOutput;
+
+        assert_eq!(ErasedOutput::new(7u32).downcast::<u32>().unwrap(), 7);
+
+        let error = ErasedOutput::new(7u32).downcast::<String>().unwrap_err();
+        match error {
+            JoinError::OutputType { expected, .. } => {
+                assert_eq!(expected, std::any::type_name::<String>())
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[test]
+    fn test_blocking_overflow() {
+        const TASK: Duration = Duration::from_millis(100);
+
+        // one blocking thread plus up to two overflow ones
+        let runtime = Builder::new()
+            .worker_threads(1)
+            .max_blocking_threads(1)
+            .blocking_overflow(2)
+            .build();
+
+        let start = Instant::now();
+        let handles: Vec<_> = (0..4)
+            .map(|_| runtime.spawn_blocking(|| std::thread::sleep(TASK)))
+            .collect();
+        // the worker thread plus three blocking ones, the fourth task waits
+        assert_eq!(runtime.metrics().blocking_threads, 4);
+        assert_eq!(runtime.metrics().overflow_blocking_threads, 2);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= TASK * 2);
+        assert!(elapsed < TASK * 3);
+
+        // the idle ones retire, only the worker thread stays
+        std::thread::sleep(TASK * 3);
+        assert_eq!(runtime.metrics().overflow_blocking_threads, 0);
+        assert_eq!(runtime.metrics().blocking_threads, 1);
+    }
+}
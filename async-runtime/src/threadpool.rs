@@ -1,92 +1,496 @@
+use futures::task::AtomicWaker;
 use log::debug;
 use std::{
+    any::Any,
+    future::Future,
     marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
+    task::{Context, Poll},
     thread,
     time::Duration,
 };
 
-use crate::runtime::{current, set_current};
+use crate::{
+    runtime::{current, set_current, AbortHandle, TaskId},
+    task::{with_wake_source, WakeSource},
+};
+
+/// What a task sends back to its `JoinHandle`: either the type-erased value
+/// or the reason why there is none.
+pub(crate) type TaskOutput = Result<ErasedOutput, JoinError>;
+
+/// The value a task returned, with its type erased. Only the `JoinHandle`
+/// created along with the task receives it, with the same type parameter,
+/// so the downcast can't fail unless that link is broken. If it ever is,
+/// the handle reports `JoinError::OutputType` rather than panicking.
+pub(crate) struct ErasedOutput {
+    value: Box<dyn Any + Send + 'static>,
+    // only kept to name the culprit when debugging, the release build
+    // relies on the TypeId check of the downcast alone
+    #[cfg(debug_assertions)]
+    type_name: &'static str,
+}
+
+impl ErasedOutput {
+    pub(crate) fn new<R: Any + Send + 'static>(value: R) -> Self {
+        Self {
+            value: Box::new(value),
+            #[cfg(debug_assertions)]
+            type_name: std::any::type_name::<R>(),
+        }
+    }
+
+    pub(crate) fn downcast<R: Any>(self) -> Result<R, JoinError> {
+        match self.value.downcast() {
+            Ok(value) => Ok(*value),
+            Err(_) => Err(JoinError::OutputType {
+                expected: std::any::type_name::<R>(),
+                #[cfg(debug_assertions)]
+                found: self.type_name,
+                #[cfg(not(debug_assertions))]
+                found: "an unknown type",
+            }),
+        }
+    }
+}
+
+/// Creates the channel a task sends its output to `JoinHandle` through.
+pub(crate) fn result_channel() -> (ResultSender, ResultReceiver) {
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    let waker = Arc::new(AtomicWaker::new());
+
+    (
+        ResultSender {
+            sender,
+            waker: waker.clone(),
+        },
+        ResultReceiver { receiver, waker },
+    )
+}
+
+pub(crate) struct ResultSender {
+    sender: crossbeam_channel::Sender<TaskOutput>,
+    // whoever awaits the JoinHandle
+    waker: Arc<AtomicWaker>,
+}
+
+impl ResultSender {
+    pub(crate) fn send(&self, output: TaskOutput) {
+        // ignore the error because there are cases where the caller doesn't
+        // need the JoinHandle thus it's dropped and the result channel is
+        // closed
+        let _ = self.sender.send(output);
+        with_wake_source(WakeSource::Channel, || self.waker.wake());
+    }
+}
+
+pub(crate) struct ResultReceiver {
+    receiver: crossbeam_channel::Receiver<TaskOutput>,
+    waker: Arc<AtomicWaker>,
+}
 
 struct BlockingTask {
-    task: Box<dyn FnOnce() -> Box<dyn std::any::Any + Send + 'static> + Send>,
-    result: Option<crossbeam_channel::Sender<Box<dyn std::any::Any + Send + 'static>>>,
+    task: Box<dyn FnOnce() -> ErasedOutput + Send>,
+    result: Option<ResultSender>,
+    // whether abort_pending may drop this task while it's still queued
+    abortable: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum JoinError {
+    #[error("task was cancelled")]
+    Cancelled,
+    /// The task was spawned in drain mode and never ran, see
+    /// `Handle::drain_mode`.
+    #[error("task was rejected, the runtime is draining")]
+    Draining,
+    /// The runtime was shut down before the task completed, see
+    /// `Handle::shutdown`.
+    #[error("runtime was shut down before the task completed")]
+    Shutdown,
+    /// The task panicked, with the payload it panicked with. A panicking
+    /// async task still takes its worker down, see
+    /// `RuntimeMetrics::live_workers`.
+    #[error("{}", self.panic_message().unwrap_or_default())]
+    Panic(Box<dyn Any + Send>),
+    /// The task stopped without producing a value and its panic couldn't be
+    /// delivered, because it was polled eagerly on the spawning thread, which
+    /// got the panic instead, or it went down with its thread.
+    #[error("task was lost before it completed")]
+    Lost,
+    /// The output of the task isn't of the type the `JoinHandle` expects,
+    /// which is a bug in the runtime.
+    #[error("task output is {found}, expected {expected}")]
+    OutputType {
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl JoinError {
+    /// A readable message for `JoinError::Panic`, from the usual `&str` and
+    /// `String` payloads, or just "task panicked" for any other payload.
+    /// `None` for the other errors.
+    pub fn panic_message(&self) -> Option<String> {
+        let JoinError::Panic(payload) = self else {
+            return None;
+        };
+
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str));
+        Some(match message {
+            Some(message) => format!("task panicked: {message}"),
+            None => "task panicked".to_owned(),
+        })
+    }
 }
 
-pub struct JoinHandle<R>(
-    crossbeam_channel::Receiver<Box<dyn std::any::Any + Send + 'static>>,
-    PhantomData<R>,
-)
+pub struct JoinHandle<R>
 where
-    R: std::any::Any + Send + 'static;
+    R: std::any::Any + Send + 'static,
+{
+    result_recv: ResultReceiver,
+    // only async tasks can be aborted, blocking tasks run to completion
+    abort_handle: Option<AbortHandle>,
+    // whether poll already returned the output
+    done: bool,
+    phantom: PhantomData<R>,
+}
 
 impl<R> JoinHandle<R>
 where
     R: std::any::Any + Send + 'static,
 {
-    pub fn new(
-        result_recv: crossbeam_channel::Receiver<Box<dyn std::any::Any + Send + 'static>>,
-    ) -> Self {
-        JoinHandle(result_recv, PhantomData)
+    pub(crate) fn new(result_recv: ResultReceiver, abort_handle: Option<AbortHandle>) -> Self {
+        JoinHandle {
+            result_recv,
+            abort_handle,
+            done: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Blocks until the task finishes. Returns `Err(JoinError::Cancelled)` if
+    /// the task was aborted before it could produce a value,
+    /// `Err(JoinError::Panic)` if it panicked and `Err(JoinError::Lost)` if
+    /// it went down with its thread.
+    pub fn join(self) -> Result<R, JoinError> {
+        // the sending side is dropped without a value only if the thread
+        // running the task unwound
+        let output = self.result_recv.receiver.recv();
+        Self::output(output.unwrap_or(Err(JoinError::Lost)))
+    }
+
+    /// Like `join`, but gives up with `Err(JoinError::Shutdown)` once
+    /// `shutdown` is disconnected, unless the task completed first.
+    pub(crate) fn join_or_shutdown(
+        self,
+        shutdown: &crossbeam_channel::Receiver<()>,
+    ) -> Result<R, JoinError> {
+        let receiver = &self.result_recv.receiver;
+        crossbeam_channel::select! {
+            recv(receiver) -> output => Self::output(output.unwrap_or(Err(JoinError::Lost))),
+            // both may be ready, the output wins
+            recv(shutdown) -> _ => match receiver.try_recv() {
+                Ok(output) => Self::output(output),
+                Err(_) => Err(JoinError::Shutdown),
+            },
+        }
+    }
+
+    fn output(output: TaskOutput) -> Result<R, JoinError> {
+        output.and_then(ErasedOutput::downcast)
+    }
+
+    /// Returns a handle that can abort the task without consuming this
+    /// `JoinHandle`, or `None` for blocking tasks which can't be aborted.
+    pub fn abort_handle(&self) -> Option<AbortHandle> {
+        self.abort_handle.clone()
+    }
+
+    /// The id of the task, `None` for blocking tasks.
+    pub fn id(&self) -> Option<TaskId> {
+        self.abort_handle.as_ref().map(AbortHandle::id)
+    }
+
+    /// Aborts the task, see `AbortHandle::abort`. Does nothing for blocking
+    /// tasks.
+    pub fn abort(&self) {
+        if let Some(abort_handle) = &self.abort_handle {
+            abort_handle.abort();
+        }
+    }
+}
+
+// the handle never pins anything
+impl<R> Unpin for JoinHandle<R> where R: std::any::Any + Send + 'static {}
+
+/// Awaiting the handle is the async version of `join`.
+impl<R> Future for JoinHandle<R>
+where
+    R: std::any::Any + Send + 'static,
+{
+    type Output = Result<R, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.done {
+            panic!("JoinHandle polled after completion");
+        }
+
+        let result_recv = &self.result_recv;
+
+        let output = match result_recv.receiver.try_recv() {
+            Ok(output) => output,
+            Err(_) => {
+                result_recv.waker.register(cx.waker());
+
+                // the task may have finished before the waker was registered
+                match result_recv.receiver.try_recv() {
+                    Ok(output) => output,
+                    Err(crossbeam_channel::TryRecvError::Empty) => return Poll::Pending,
+                    Err(crossbeam_channel::TryRecvError::Disconnected) => Err(JoinError::Lost),
+                }
+            }
+        };
+
+        self.done = true;
+        Poll::Ready(Self::output(output))
+    }
+}
+
+/// Returned by `Handle::spawn_guarded`. Works like a `JoinHandle` except
+/// that dropping the guard before the task finishes aborts the task, so the
+/// task can't outlive its owner by accident.
+pub struct TaskGuard<R>
+where
+    R: std::any::Any + Send + 'static,
+{
+    // None once the task is joined or the guard released
+    handle: Option<JoinHandle<R>>,
+}
+
+impl<R> TaskGuard<R>
+where
+    R: std::any::Any + Send + 'static,
+{
+    pub(crate) fn new(handle: JoinHandle<R>) -> Self {
+        Self {
+            handle: Some(handle),
+        }
+    }
+
+    /// Blocks until the task finishes, see `JoinHandle::join`.
+    pub fn join(mut self) -> Result<R, JoinError> {
+        self.handle.take().unwrap().join()
     }
 
-    pub fn join(self) -> R {
-        *self.0.recv().unwrap().downcast().unwrap()
+    /// Disarms the guard: the task is no longer aborted on drop and keeps
+    /// running detached unless the returned handle is used to abort it.
+    pub fn release(mut self) -> JoinHandle<R> {
+        self.handle.take().unwrap()
+    }
+
+    pub fn abort_handle(&self) -> Option<AbortHandle> {
+        self.handle.as_ref().and_then(JoinHandle::abort_handle)
+    }
+
+    pub fn id(&self) -> Option<TaskId> {
+        self.handle.as_ref().and_then(JoinHandle::id)
     }
 }
 
+impl<R> Future for TaskGuard<R>
+where
+    R: std::any::Any + Send + 'static,
+{
+    type Output = Result<R, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let handle = self
+            .handle
+            .as_mut()
+            .expect("TaskGuard polled after completion");
+        let output = std::task::ready!(Pin::new(handle).poll(cx));
+        self.handle = None;
+        Poll::Ready(output)
+    }
+}
+
+impl<R> Drop for TaskGuard<R>
+where
+    R: std::any::Any + Send + 'static,
+{
+    fn drop(&mut self) {
+        // aborting a task that already finished does nothing so there's no
+        // need to check
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+}
+
+// TODO is this the right timeout value?
+// how long a thread waits for a task before exiting
+const KEEP_ALIVE: Duration = Duration::from_millis(100);
+
 /// Pool of threads used for blocking tasks.
 pub struct ThreadPool {
     capacity: usize,
+    // how many threads may be started on top of `capacity` while tasks are
+    // queued with no idle thread to take them
+    overflow: usize,
     task_recv: crossbeam_channel::Receiver<BlockingTask>,
     task_send: crossbeam_channel::Sender<BlockingTask>,
     num_threads: Arc<AtomicUsize>,
+    overflow_threads: Arc<AtomicUsize>,
+    // threads running a task, of either kind
+    busy_threads: Arc<AtomicUsize>,
 }
 
 impl ThreadPool {
-    pub fn new(capacity: usize) -> Self {
+    pub fn new(capacity: usize, overflow: usize) -> Self {
         let (task_send, task_recv) = crossbeam_channel::unbounded();
         ThreadPool {
             capacity,
+            overflow,
             task_recv,
             task_send,
             num_threads: Arc::new(AtomicUsize::new(0)),
+            overflow_threads: Arc::new(AtomicUsize::new(0)),
+            busy_threads: Arc::new(AtomicUsize::new(0)),
         }
     }
 
+    /// Number of threads in the pool, the overflow ones included.
+    pub fn num_threads(&self) -> usize {
+        self.num_threads.load(Ordering::Relaxed) + self.overflow_threads()
+    }
+
+    /// Number of threads started beyond the capacity of the pool to drain a
+    /// backlog, see `Builder::blocking_overflow`.
+    pub fn overflow_threads(&self) -> usize {
+        self.overflow_threads.load(Ordering::Relaxed)
+    }
+
+    /// Number of threads running a task right now.
+    pub fn busy_threads(&self) -> usize {
+        self.busy_threads.load(Ordering::Relaxed)
+    }
+
+    /// Number of tasks waiting for a thread to pick them up.
+    pub fn queue_depth(&self) -> usize {
+        self.task_recv.len()
+    }
+
     pub fn spawn_blocking<F, R>(&self, task: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: std::any::Any + Send + 'static,
+    {
+        self.spawn_task(task, true)
+    }
+
+    /// Same as `spawn_blocking` but the task survives `abort_pending`. Used
+    /// for the runtime's own long running jobs such as the worker loops.
+    pub(crate) fn spawn_unabortable<F, R>(&self, task: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: std::any::Any + Send + 'static,
+    {
+        self.spawn_task(task, false)
+    }
+
+    fn spawn_task<F, R>(&self, task: F, abortable: bool) -> JoinHandle<R>
     where
         F: FnOnce() -> R + Send + 'static,
         R: std::any::Any + Send + 'static,
     {
         // TODO for correctness, mutex should be used here
-        let (result_send, result_recv) = crossbeam_channel::bounded(1);
+        let (result_send, result_recv) = result_channel();
 
         self.task_send
             .send(BlockingTask {
-                task: Box::new(|| Box::new(task())),
+                task: Box::new(|| ErasedOutput::new(task())),
                 result: Some(result_send),
+                abortable,
             })
             .unwrap();
 
         if self.num_threads.load(Ordering::Relaxed) < self.capacity {
-            self.spawn_thread();
+            self.spawn_thread(false);
+        } else if self.has_backlog() && self.reserve_overflow_thread() {
+            self.spawn_thread(true);
+        }
+
+        JoinHandle::new(result_recv, None)
+    }
+
+    /// Drops every queued task that no thread has started yet and resolves
+    /// their `JoinHandle`s to `Err(JoinError::Cancelled)`. Tasks that are
+    /// already running are left alone and complete normally. Returns the
+    /// number of dropped tasks.
+    pub fn abort_pending(&self) -> usize {
+        let mut aborted = 0;
+        let mut kept = Vec::new();
+
+        for task in self.task_recv.try_iter() {
+            if !task.abortable {
+                kept.push(task);
+                continue;
+            }
+
+            if let Some(result_sender) = task.result {
+                result_sender.send(Err(JoinError::Cancelled));
+            }
+            aborted += 1;
+        }
+
+        for task in kept {
+            self.task_send.send(task).unwrap();
         }
 
-        // *result_recv.recv().unwrap().downcast::<R>().unwrap()
-        JoinHandle(result_recv, PhantomData)
+        debug!("aborted {} pending blocking tasks", aborted);
+        aborted
+    }
+
+    // whether there are more queued tasks than idle threads to take them
+    fn has_backlog(&self) -> bool {
+        let idle = self
+            .num_threads()
+            .saturating_sub(self.busy_threads.load(Ordering::Relaxed));
+        self.task_recv.len() > idle
+    }
+
+    // counts one more overflow thread unless the cap is reached
+    fn reserve_overflow_thread(&self) -> bool {
+        self.overflow_threads
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |threads| {
+                (threads < self.overflow).then_some(threads + 1)
+            })
+            .is_ok()
     }
 
-    fn spawn_thread(&self) {
-        debug!("spawning new thread");
+    // an overflow thread must have been reserved beforehand, the regular
+    // ones are counted here
+    fn spawn_thread(&self, overflow: bool) {
+        debug!("spawning new thread, overflow: {}", overflow);
         let task_recv = self.task_recv.clone();
 
         // TODO is Box<dyn Fn()> the right type here?
-        self.num_threads.fetch_add(1, Ordering::Relaxed);
-        let num_threads = self.num_threads.clone();
+        let num_threads = if overflow {
+            self.overflow_threads.clone()
+        } else {
+            self.num_threads.fetch_add(1, Ordering::Relaxed);
+            self.num_threads.clone()
+        };
+        let busy_threads = self.busy_threads.clone();
 
         // get the current runtime handle and pass it to the thread
         let handle = current();
@@ -98,27 +502,36 @@ impl ThreadPool {
                 set_current(handle);
 
                 debug!("blocking thread started");
-                loop {
-                    // TODO is this the right timeout value?
-                    match task_recv.recv_timeout(Duration::from_millis(100)) {
-                        Ok(task) => {
-                            debug!("blocking thread pool received new task");
-                            let result = (task.task)();
-                            if let Some(result_sender) = task.result {
-                                // ignore the error because there are cases
-                                // where the caller doesn't need the JoinHandle
-                                // thus it's dropped and the result channel is
-                                // closed before the result is sent
-                                let _ = result_sender.send(result);
-                            }
-                        }
-                        Err(_) => break, // break and exit the thread
+                let _thread = CountGuard(&num_threads);
+                // exit the thread once no task arrives within the timeout,
+                // which also retires the overflow threads once the backlog
+                // is drained
+                while let Ok(task) = task_recv.recv_timeout(KEEP_ALIVE) {
+                    debug!("blocking thread pool received new task");
+                    busy_threads.fetch_add(1, Ordering::Relaxed);
+                    let busy = CountGuard(&busy_threads);
+                    // the thread survives a panicking task, its handle gets
+                    // the payload
+                    let result =
+                        panic::catch_unwind(AssertUnwindSafe(task.task)).map_err(JoinError::Panic);
+                    drop(busy);
+                    if let Some(result_sender) = task.result {
+                        result_sender.send(result);
                     }
                 }
 
                 debug!("blocking thread exiting");
-                num_threads.fetch_sub(1, Ordering::Relaxed);
             })
             .unwrap();
     }
 }
+
+// decrements the counter when dropped, so that it's also given back when
+// the thread unwinds
+struct CountGuard<'a>(&'a AtomicUsize);
+
+impl Drop for CountGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Condvar, Mutex},
+    task::Waker,
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+
+use crate::task::{with_wake_source, WakeSource};
+
+// how long the timer thread waits with nothing to fire before checking
+// whether the runtime is still around
+const IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Identifies a registered deadline: the tick it fires at, then a unique
+/// number to tell apart the deadlines sharing that tick.
+pub(crate) type EntryKey = (u64, u64);
+
+/// Fires the wakers of the registered deadlines from the timer thread.
+///
+/// Time is cut into ticks of `granularity` and deadlines are rounded up to
+/// the next tick, so the deadlines falling into the same tick are fired
+/// together by a single wakeup of the thread. A sleep may thus last up to
+/// one tick longer than asked.
+pub(crate) struct Timer {
+    state: Mutex<State>,
+    condvar: Condvar,
+    granularity: Duration,
+    origin: Instant,
+}
+
+struct State {
+    entries: BTreeMap<EntryKey, Waker>,
+    next_id: u64,
+    // the tick the timer thread is sleeping until, None when it's waiting
+    // for something to be registered
+    next_wakeup: Option<u64>,
+}
+
+impl Timer {
+    /// Starts the timer thread, which exits once the runtime is gone.
+    pub(crate) fn start(granularity: Duration) -> Arc<Self> {
+        let timer = Arc::new(Self {
+            state: Mutex::new(State {
+                entries: BTreeMap::new(),
+                next_id: 0,
+                next_wakeup: None,
+            }),
+            condvar: Condvar::new(),
+            // ticks are counted in whole nanoseconds
+            granularity: granularity.max(Duration::from_nanos(1)),
+            origin: Instant::now(),
+        });
+
+        let weak = Arc::downgrade(&timer);
+
+        thread::Builder::new()
+            .name("timer".into())
+            .spawn(move || loop {
+                let Some(timer) = weak.upgrade() else {
+                    debug!("runtime dropped, timer thread exiting");
+                    break;
+                };
+
+                timer.turn();
+            })
+            .unwrap();
+
+        timer
+    }
+
+    /// Registers `waker` to be woken once `deadline` is reached.
+    pub(crate) fn register(&self, deadline: Instant, waker: Waker) -> EntryKey {
+        let tick = self.tick_of(deadline);
+
+        let mut state = self.state.lock().unwrap();
+        let key = (tick, state.next_id);
+        state.next_id += 1;
+        state.entries.insert(key, waker);
+
+        // only bother the thread if it would otherwise sleep past this one
+        if state.next_wakeup.is_none_or(|next| tick < next) {
+            state.next_wakeup = Some(tick);
+            self.condvar.notify_one();
+        }
+
+        key
+    }
+
+    /// Replaces the waker of a registered deadline. Returns false if the
+    /// deadline has already fired.
+    pub(crate) fn update(&self, key: EntryKey, waker: &Waker) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.entries.get_mut(&key) {
+            Some(registered) => {
+                if !registered.will_wake(waker) {
+                    registered.clone_from(waker);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The registered deadlines, soonest first, rounded up to the tick they
+    /// fire at.
+    #[cfg(feature = "task-registry")]
+    pub(crate) fn deadlines(&self) -> Vec<Instant> {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .keys()
+            .map(|(tick, _)| self.instant_of(*tick))
+            .collect()
+    }
+
+    pub(crate) fn cancel(&self, key: EntryKey) {
+        self.state.lock().unwrap().entries.remove(&key);
+    }
+
+    /// The first tick at or after the instant, what a deadline at that
+    /// instant is registered under.
+    pub(crate) fn tick_of(&self, instant: Instant) -> u64 {
+        let nanos = instant.saturating_duration_since(self.origin).as_nanos();
+        nanos.div_ceil(self.granularity.as_nanos()) as u64
+    }
+
+    fn instant_of(&self, tick: u64) -> Instant {
+        self.origin + Duration::from_nanos(tick * self.granularity.as_nanos() as u64)
+    }
+
+    /// Fires the due deadlines, then sleeps until the next one.
+    fn turn(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        // the ticks that have fully started by now
+        let now = self.origin.elapsed().as_nanos() / self.granularity.as_nanos();
+        let pending = state.entries.split_off(&(now as u64 + 1, 0));
+        let due = std::mem::replace(&mut state.entries, pending);
+
+        state.next_wakeup = state.entries.keys().next().map(|(tick, _)| *tick);
+
+        if !due.is_empty() {
+            drop(state);
+            debug!("timer firing {} deadlines", due.len());
+            with_wake_source(WakeSource::Timer, || {
+                for waker in due.into_values() {
+                    waker.wake();
+                }
+            });
+            return;
+        }
+
+        let timeout = match state.next_wakeup {
+            Some(tick) => self
+                .instant_of(tick)
+                .saturating_duration_since(Instant::now())
+                .min(IDLE_TIMEOUT),
+            None => IDLE_TIMEOUT,
+        };
+
+        drop(self.condvar.wait_timeout(state, timeout).unwrap());
+    }
+}
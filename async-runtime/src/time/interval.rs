@@ -0,0 +1,115 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::{future::poll_fn, ready};
+
+use super::Sleep;
+use crate::task::AwaitSite;
+
+/// Ticks every `period`, starting right away, on the timer of the current
+/// runtime. Panics if `period` is zero.
+#[track_caller]
+pub fn interval(period: Duration) -> Interval {
+    interval_at(Instant::now(), period)
+}
+
+/// Same as `interval` but the first tick is at `start`.
+#[track_caller]
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(period > Duration::ZERO, "interval period must be non-zero");
+
+    Interval {
+        delay: Sleep::new(start, AwaitSite::new("interval tick")),
+        period,
+        missed_tick_behavior: MissedTickBehavior::default(),
+    }
+}
+
+/// What an `Interval` does when ticks were missed, i.e. when the next tick
+/// was already due by the time a tick was taken, because the consumer was
+/// busy for longer than a period.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MissedTickBehavior {
+    /// Fires the missed ticks right away, one after the other, until it
+    /// has caught up with the original schedule.
+    #[default]
+    Burst,
+    /// Starts a new schedule: the next tick is a period after the late
+    /// tick was taken, and so on from there.
+    Delay,
+    /// Drops the missed ticks and carries on with the original schedule at
+    /// the next tick that's still in the future.
+    Skip,
+}
+
+impl MissedTickBehavior {
+    // the tick after `tick` which was taken late, at `now`
+    pub(crate) fn next_tick(self, tick: Instant, now: Instant, period: Duration) -> Instant {
+        match self {
+            Self::Burst => tick + period,
+            Self::Delay => now + period,
+            Self::Skip => {
+                // in u128, a short period and a long stall miss more ticks
+                // than fit in a u32
+                let period_nanos = period.as_nanos();
+                let missed = (now - tick).as_nanos() / period_nanos;
+                u64::try_from((missed + 1) * period_nanos)
+                    .ok()
+                    .and_then(|nanos| tick.checked_add(Duration::from_nanos(nanos)))
+                    .unwrap_or(now + period)
+            }
+        }
+    }
+}
+
+/// Timer returned by `interval` and `interval_at`.
+pub struct Interval {
+    // fires at the next tick
+    delay: Sleep,
+    period: Duration,
+    missed_tick_behavior: MissedTickBehavior,
+}
+
+impl Interval {
+    /// Waits for the next tick and returns when it was scheduled, which is
+    /// earlier than now if the tick is late.
+    pub async fn tick(&mut self) -> Instant {
+        poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        ready!(Pin::new(&mut self.delay).poll(cx));
+
+        let tick = self.delay.deadline();
+        let now = Instant::now();
+        let next = if now >= tick + self.period {
+            self.missed_tick_behavior.next_tick(tick, now, self.period)
+        } else {
+            tick + self.period
+        };
+        self.delay.reset(next);
+
+        Poll::Ready(tick)
+    }
+
+    /// Starts the schedule over, with the next tick a period from now.
+    pub fn reset(&mut self) {
+        self.delay.reset(Instant::now() + self.period);
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.missed_tick_behavior
+    }
+
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.missed_tick_behavior = behavior;
+    }
+}
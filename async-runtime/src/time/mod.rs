@@ -0,0 +1,11 @@
+//! Timers driven by a dedicated timer thread of the runtime.
+
+mod driver;
+mod interval;
+mod sleep;
+mod timeout;
+
+pub(crate) use driver::Timer;
+pub use interval::{interval, interval_at, Interval, MissedTickBehavior};
+pub use sleep::{sleep, sleep_until, Sleep};
+pub use timeout::{timeout, timeout_at, Elapsed, Timeout};
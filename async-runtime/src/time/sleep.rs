@@ -0,0 +1,114 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use super::{driver::EntryKey, Timer};
+use crate::{runtime::current, task::AwaitSite};
+
+/// Waits until `duration` has elapsed, on the timer of the current runtime.
+///
+/// Deadlines are rounded up to the timer granularity, see
+/// `Builder::timer_granularity`.
+#[track_caller]
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// Waits until `deadline` is reached, on the timer of the current runtime.
+#[track_caller]
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep::new(deadline, AwaitSite::new("sleep"))
+}
+
+/// Future returned by `sleep` and `sleep_until`.
+pub struct Sleep {
+    timer: Arc<Timer>,
+    deadline: Instant,
+    // registered with the timer on the first pending poll
+    entry: Option<EntryKey>,
+    site: AwaitSite,
+}
+
+impl Sleep {
+    pub(crate) fn new(deadline: Instant, site: AwaitSite) -> Self {
+        Self {
+            timer: current().timer(),
+            deadline,
+            entry: None,
+            site,
+        }
+    }
+
+    /// The instant the sleep completes at.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Whether the deadline has passed, i.e. the next poll is ready. Checks
+    /// the clock rather than whether the timer fired, so it's accurate even
+    /// before the sleep is first polled.
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Moves the deadline, the sleep can be awaited again afterwards even if
+    /// it had completed.
+    ///
+    /// Pushing the deadline back is cheap, the timer isn't touched until the
+    /// old deadline is reached, so a timeout reset on every packet doesn't
+    /// churn the timer. The task may be woken once at the old deadline
+    /// though.
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+
+        let Some(entry) = self.entry else {
+            return;
+        };
+        // a later deadline keeps the entry, which fires early and gets
+        // registered again with the new deadline by the poll it wakes.
+        // An earlier one can't wait, it's registered on the next poll
+        if self.timer.tick_of(deadline) < entry.0 {
+            self.entry = None;
+            self.timer.cancel(entry);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.is_elapsed() {
+            if let Some(entry) = self.entry.take() {
+                self.timer.cancel(entry);
+            }
+            return Poll::Ready(());
+        }
+
+        match self.entry {
+            // still registered, make sure we wake the right task
+            Some(entry) if self.timer.update(entry, cx.waker()) => {}
+            // not registered yet, or the timer fired a bit early because of
+            // clock jitter, register again
+            _ => {
+                let entry = self.timer.register(self.deadline, cx.waker().clone());
+                self.entry = Some(entry);
+            }
+        }
+
+        self.site.pending();
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            self.timer.cancel(entry);
+        }
+    }
+}
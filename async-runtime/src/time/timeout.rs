@@ -0,0 +1,87 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::ready;
+use pin_project_lite::pin_project;
+
+use super::Sleep;
+use crate::{metrics::Metrics, runtime::current, task::AwaitSite};
+
+/// Returned by `Timeout` when the future didn't complete in time.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("deadline has elapsed")]
+pub struct Elapsed(());
+
+/// Runs `future` for at most `duration`, on the timer of the current
+/// runtime. Resolves to `Err(Elapsed)` and drops the future if it isn't
+/// done by then.
+///
+/// How many timeouts complete in time and how many elapse shows in
+/// `RuntimeMetrics::timeouts`, see `Timeout::label` to tell call sites
+/// apart.
+#[track_caller]
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    timeout_at(Instant::now() + duration, future)
+}
+
+/// Same as `timeout` with a deadline instead of a duration.
+#[track_caller]
+pub fn timeout_at<F: Future>(deadline: Instant, future: F) -> Timeout<F> {
+    Timeout {
+        future: Some(future),
+        delay: Sleep::new(deadline, AwaitSite::new("timeout")),
+        metrics: current().shared_metrics(),
+        label: None,
+    }
+}
+
+pin_project! {
+    /// Future returned by `timeout` and `timeout_at`.
+    pub struct Timeout<F> {
+        // dropped once the timeout resolves
+        #[pin]
+        future: Option<F>,
+        delay: Sleep,
+        metrics: Arc<Metrics>,
+        label: Option<&'static str>,
+    }
+}
+
+impl<F> Timeout<F> {
+    /// Counts this timeout under `label` in the metrics, e.g. the name of
+    /// the operation it guards.
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.label = Some(label);
+        self
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        let future = this
+            .future
+            .as_mut()
+            .as_pin_mut()
+            .expect("Timeout polled after completion");
+
+        if let Poll::Ready(output) = future.poll(cx) {
+            this.future.set(None);
+            this.metrics.timeout_resolved(*this.label, false);
+            return Poll::Ready(Ok(output));
+        }
+
+        ready!(Pin::new(this.delay).poll(cx));
+        this.future.set(None);
+        this.metrics.timeout_resolved(*this.label, true);
+        Poll::Ready(Err(Elapsed(())))
+    }
+}
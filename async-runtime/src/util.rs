@@ -0,0 +1,79 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+/// Cheap per-thread xorshift returning a number in `0..n`. It's only meant to
+/// spread things like polling order or backoff, not for anything that needs
+/// real randomness. Each thread starts from its own seed so that threads
+/// don't draw the same sequence, e.g. back off in lockstep.
+pub fn random(n: usize) -> usize {
+    thread_local! {
+        static STATE: Cell<u32> = Cell::new(seed());
+    }
+
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        state.set(x);
+        x as usize % n
+    })
+}
+
+// different for every thread, RandomState is keyed randomly per thread
+fn seed() -> u32 {
+    let mut hasher = RandomState::new().build_hasher();
+    std::thread::current().id().hash(&mut hasher);
+    Instant::now().hash(&mut hasher);
+    let hash = hasher.finish();
+    // xorshift gets stuck on zero
+    ((hash ^ (hash >> 32)) as u32).max(1)
+}
+
+/// FNV-1a, for hashes that must not change between runs or builds, unlike
+/// those of `DefaultHasher`.
+pub(crate) struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Whether `thread_cpu_time` can ever return something on this platform.
+pub(crate) const THREAD_CPU_TIME_SUPPORTED: bool = cfg!(target_os = "linux");
+
+/// CPU time consumed by the calling thread so far.
+#[cfg(target_os = "linux")]
+pub(crate) fn thread_cpu_time() -> Option<Duration> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: the pointer is valid for writes for the duration of the call
+    let ret = unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) };
+
+    (ret == 0).then(|| Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn thread_cpu_time() -> Option<Duration> {
+    None
+}
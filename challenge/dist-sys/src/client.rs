@@ -5,7 +5,7 @@ use std::{
 
 use futures::Future;
 
-use crate::proto::{Body, Message, Protocol, Request, Response, PROTOCOL};
+use crate::proto::{Body, Message, Request, Response, PROTOCOL};
 
 #[derive(Debug)]
 struct Inner {
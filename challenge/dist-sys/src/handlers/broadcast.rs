@@ -41,10 +41,8 @@ pub async fn handle(app: &mut App, r: &Request) -> Response {
                                 let _ = send.try_send(());
                             }
                         });
-                        if let Ok(sig) = recv.try_next() {
-                            if let Some(_) = sig {
-                                break;
-                            }
+                        if let Ok(Some(_)) = recv.try_next() {
+                            break;
                         }
                         // TODO implement backoff
                         futures_timer::Delay::new(std::time::Duration::from_millis(100)).await;
@@ -7,7 +7,7 @@ use crate::{
     App,
 };
 
-pub async fn handle(app: &mut App, r: &Request) -> Response {
+pub async fn handle(app: &mut App, _: &Request) -> Response {
     // generate a 64 bit snowflake ID
     // https://en.wikipedia.org/wiki/Snowflake_ID
 
@@ -26,6 +26,7 @@ type App = Arc<Inner>;
 #[derive(Debug)]
 struct Context {
     node_id: String,
+    #[allow(dead_code)]
     node_ids: Vec<String>,
 }
 
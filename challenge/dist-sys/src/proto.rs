@@ -1,13 +1,11 @@
 use std::{
-    borrow::BorrowMut,
-    cell::RefCell,
     collections::HashMap,
     io::{self, Write},
-    sync::{Arc, OnceLock, RwLock},
+    sync::OnceLock,
 };
 
 use async_runtime::runtime::current;
-use futures::{future::join, lock::Mutex, Future, SinkExt, StreamExt};
+use futures::{lock::Mutex, Future, StreamExt};
 use serde_json::{Map, Value};
 
 use crate::{client::RequestFuture, stdin::StdinListener};
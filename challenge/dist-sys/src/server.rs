@@ -1,7 +1,6 @@
-use futures::{join, Future};
+use futures::Future;
 
 use crate::{
-    client::request,
     proto::{Protocol, PROTOCOL},
     Request, Response,
 };